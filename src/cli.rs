@@ -1,6 +1,10 @@
 use clap::{Parser, ValueEnum};
+use minecraft_world_trimmer::commands::MAX_LOADED_RADIUS;
+use minecraft_world_trimmer::{Area, CompressionScheme, RegionFormat, Ticks};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ord;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(Parser)]
 #[command(
@@ -14,13 +18,101 @@ pub struct Cli {
     #[arg(value_enum, required = true)]
     pub mode: Mode,
 
-    /// Path to your Minecraft Worlds containing `level.dat` file
-    #[arg(required = true)]
+    /// Path to your Minecraft Worlds containing `level.dat` file, or to your server directories
+    /// when using `--server`. Can be set with `worlds` in the configuration file instead.
     pub world_paths: Vec<PathBuf>,
 
-    /// Compression level when writing region files
-    #[arg(short, long, default_value = "6", value_parser = validate_compression_level)]
-    pub compression_level: u32,
+    /// TOML file holding the world paths and settings, overridden by the command-line flags
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Treat the given paths as Bukkit, Spigot or Paper server directories and find their worlds
+    /// from `server.properties` and `bukkit.yml`
+    #[arg(long, overrides_with = "no_server")]
+    pub server: bool,
+
+    /// Treat the given paths as world directories, overriding `server` in the configuration file
+    #[arg(long, overrides_with = "server")]
+    pub no_server: bool,
+
+    /// Compression level when recompressing chunks [default: 6]
+    #[arg(short, long, value_parser = validate_compression_level)]
+    pub compression_level: Option<u32>,
+
+    /// Directory where the sectors of corrupted chunks are extracted for manual recovery.
+    /// Corrupted chunks are always kept in the world.
+    #[arg(long)]
+    pub quarantine_dir: Option<PathBuf>,
+
+    /// Compression algorithm used when recompressing chunks [default: zlib]
+    #[arg(long, value_enum)]
+    pub compression_scheme: Option<CompressionAlgorithm>,
+
+    /// Recompress every kept chunk with the given compression scheme and level.
+    /// By default, the original compressed data of unchanged chunks is copied as-is, which is much faster.
+    #[arg(long, overrides_with = "no_recompress")]
+    pub recompress: bool,
+
+    /// Copy the compressed data of unchanged chunks as-is, overriding `recompress` in the
+    /// configuration file
+    #[arg(long, overrides_with = "recompress")]
+    pub no_recompress: bool,
+
+    /// Delete the chunks inhabited for less than this time, given in ticks or as a duration such
+    /// as `5m`. Prefix it with a dimension id to only apply it to that dimension, such as
+    /// `minecraft:the_nether=30s`. Can be repeated.
+    #[arg(long, value_name = "[DIMENSION=]TIME")]
+    pub min_inhabited_time: Vec<MinInhabitedTime>,
+
+    /// Move chunks stored in the wrong region file or slot to where they belong, in write mode.
+    /// Chunks are left in place if their slot is already used.
+    #[arg(long, overrides_with = "no_fix_misplaced")]
+    pub fix_misplaced: bool,
+
+    /// Leave misplaced chunks where they are, overriding `fix-misplaced` in the configuration file
+    #[arg(long, overrides_with = "fix_misplaced")]
+    pub no_fix_misplaced: bool,
+
+    /// Copy region files to this directory before they are modified or deleted in write and
+    /// recover modes,
+    /// or into a tar archive if the path ends with `.tar`. A manifest lists the original path
+    /// and the SHA-256 hash of each copied file.
+    /// In restore mode, the backup of the run to restore.
+    #[arg(long)]
+    pub backup: Option<PathBuf>,
+
+    /// Address of the RCON server of a running server, such as `localhost:25575`, in write mode.
+    /// Its saves are turned off during the run, and regions around players and the spawn are
+    /// left untouched.
+    #[arg(long)]
+    pub rcon: Option<String>,
+
+    /// Password of the RCON server, set with `rcon.password` in `server.properties`
+    #[arg(long)]
+    pub rcon_password: Option<String>,
+
+    /// Regions within this many chunks of a player or of the spawn are considered loaded by the
+    /// server when using `--rcon`, up to 1024 [default: 32]
+    #[arg(long, value_parser = validate_loaded_radius)]
+    pub loaded_radius: Option<u32>,
+
+    /// Only process the region files of this dimension, such as `minecraft:the_nether`.
+    /// Can be repeated.
+    #[arg(long)]
+    pub dimension: Vec<String>,
+
+    /// Only restore the region files overlapping this area, given as `<x1>,<z1>,<x2>,<z2>` block
+    /// coordinates, in restore mode
+    #[arg(long, allow_hyphen_values = true)]
+    pub area: Option<Area>,
+
+    /// Restore files even if they were modified since the run, such as by the game
+    #[arg(long)]
+    pub force: bool,
+
+    /// Region format the world is converted to in convert mode
+    #[arg(long, value_enum, required_if_eq("mode", "convert"))]
+    pub format: Option<RegionFileFormat>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -29,14 +121,121 @@ pub enum Mode {
     Check,
 
     /// Optimizes the world by deleting unused region files and chunks.
-    /// This is a destructive process, make sure to make a backup of your worlds before running.
-    /// Also make sure the world is not loaded by the game as this will corrupt the world.
+    /// This is a destructive process, make sure to make a backup of your worlds before running,
+    /// or use `--backup`.
+    /// Worlds open in the game or a server are refused, as writing them would corrupt them,
+    /// unless the server is reached with `--rcon`.
     Write,
+
+    /// Converts region files to the format given with `--format`, without deleting any chunk.
+    /// Entities and points of interest regions are converted along with the terrain.
+    Convert,
+
+    /// Scans the sectors of region files with a damaged location table to find every chunk they
+    /// still hold, keeping the newest copy of each chunk, and rebuilds their location table.
+    /// Run it before the write mode, which deletes regions without any readable chunk.
+    Recover,
+
+    /// Puts back the region files copied in the backup given with `--backup` by a write or
+    /// recover run.
+    /// Files modified since the run are left untouched unless `--force` is set.
+    Restore,
+
+    /// Prints the configuration merged from `--config` and the command-line flags, without
+    /// touching any world
+    PrintConfig,
+}
+
+#[derive(
+    Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default, Debug, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    /// GZip (RFC1952), rarely used by the game
+    Gzip,
+
+    /// Zlib (RFC1950), the default used by the game
+    #[default]
+    Zlib,
+
+    /// No compression, useful when the file system already compresses data (eg. btrfs or zfs).
+    /// The compression level is ignored.
+    None,
+
+    /// LZ4, can be used by servers since 24w04a with the `region-file-compression` property.
+    /// The compression level is ignored.
+    Lz4,
+}
+
+impl From<CompressionAlgorithm> for CompressionScheme {
+    fn from(algorithm: CompressionAlgorithm) -> Self {
+        match algorithm {
+            CompressionAlgorithm::Gzip => CompressionScheme::Gzip,
+            CompressionAlgorithm::Zlib => CompressionScheme::Zlib,
+            CompressionAlgorithm::None => CompressionScheme::None,
+            CompressionAlgorithm::Lz4 => CompressionScheme::Lz4,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum RegionFileFormat {
+    /// Anvil `.mca` region files, used by the game
+    Anvil,
+
+    /// zstd compressed `.linear` region files, used by some server forks.
+    /// The compression level is used as the zstd level.
+    Linear,
+}
+
+impl From<RegionFileFormat> for RegionFormat {
+    fn from(format: RegionFileFormat) -> Self {
+        match format {
+            RegionFileFormat::Anvil => RegionFormat::Anvil,
+            RegionFileFormat::Linear => RegionFormat::Linear,
+        }
+    }
 }
 
+/// A minimum inhabited time, optionally for a single dimension
+#[derive(Clone)]
+pub struct MinInhabitedTime {
+    pub dimension: Option<String>,
+    pub time: Ticks,
+}
+
+impl FromStr for MinInhabitedTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.rsplit_once('=') {
+            Some((dimension, time)) => Ok(Self {
+                dimension: Some(dimension.to_string()),
+                time: time.parse()?,
+            }),
+            None => Ok(Self {
+                dimension: None,
+                time: s.parse()?,
+            }),
+        }
+    }
+}
+
+pub const MAX_COMPRESSION_LEVEL: u32 = 9;
+pub const COMPRESSION_LEVEL_ERROR: &str = "Compression level must be an integer between 0 and 9";
+
 fn validate_compression_level(s: &str) -> Result<u32, String> {
     match s.parse::<u32>() {
-        Ok(level) if level <= 9 => Ok(level),
-        _ => Err("Compression level must be an integer between 0 and 9".to_string()),
+        Ok(level) if level <= MAX_COMPRESSION_LEVEL => Ok(level),
+        _ => Err(COMPRESSION_LEVEL_ERROR.to_string()),
+    }
+}
+
+pub const LOADED_RADIUS_ERROR: &str = "Loaded radius must be an integer between 0 and 1024";
+
+fn validate_loaded_radius(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(radius) if radius <= MAX_LOADED_RADIUS => Ok(radius),
+        _ => Err(LOADED_RADIUS_ERROR.to_string()),
     }
 }
@@ -0,0 +1,624 @@
+use crate::commands::journal::{escape, unescape};
+use crate::commands::region_writer::{
+    remove_region, save_region, save_region_with, write_atomically,
+};
+use crate::region_loader::chunk_loader::compression_scheme::CompressionScheme;
+use crate::region_loader::region::Region;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use thiserror::Error;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.txt";
+const MANIFEST_HEADER: &str = "# original sha256\tbackup path\toriginal path\twritten sha256";
+
+/// A file modified by the write mode, as listed in the manifest of the backup. Hashes are `None`
+/// when the file did not exist, either before or after the run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub original_sha256: Option<String>,
+    /// Path of the copy, relative to the backup directory or archive
+    pub path: Option<String>,
+    pub original_path: PathBuf,
+    pub written_sha256: Option<String>,
+}
+
+/// Copies the files modified or deleted by the write mode, either in a directory or in a tar
+/// archive when the path ends with `.tar`. Each file is only copied the first time it is
+/// modified, and listed in a manifest with its original path, the SHA-256 hash of the copy and
+/// the hash of the file written by the run.
+pub struct Backup {
+    target: BackupTarget,
+    files: Mutex<HashMap<PathBuf, ManifestEntry>>,
+}
+
+enum BackupTarget {
+    Directory {
+        directory: PathBuf,
+        manifest: Mutex<File>,
+    },
+    /// The manifest entries are added to the archive as they are written, as files all named
+    /// `manifest.txt`, so that the archive can be restored or resumed even if the run is
+    /// interrupted
+    Tar(Mutex<tar::Builder<File>>),
+}
+
+impl Backup {
+    /// Creates the backup. A backup directory can only be reused when resuming an interrupted
+    /// run, so that the copies of the files modified by that run are not overwritten.
+    pub fn create(path: &Path, is_resuming: bool) -> Result<Self, BackupError> {
+        if is_tar_archive(path) {
+            return Backup::open_tar_archive(path, is_resuming);
+        }
+
+        let manifest_path = path.join(MANIFEST_FILE_NAME);
+        let content = match std::fs::read_to_string(&manifest_path) {
+            Ok(_) if !is_resuming => return Err(BackupError::AlreadyExists(path.to_path_buf())),
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(BackupError::Io(err)),
+        };
+        // Drop the entry that was being written when the run got interrupted
+        let complete_length = content.rfind('\n').map_or(0, |end| end + 1);
+        let files = parse_manifest(&content[..complete_length])
+            .into_iter()
+            .map(|entry| (entry.original_path.clone(), entry))
+            .collect::<HashMap<_, _>>();
+
+        std::fs::create_dir_all(path).map_err(BackupError::Io)?;
+        let mut manifest = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&manifest_path)
+            .map_err(BackupError::Io)?;
+        if files.is_empty() {
+            manifest
+                .set_len(0)
+                .and_then(|_| writeln!(manifest, "{MANIFEST_HEADER}"))
+                .map_err(BackupError::Io)?;
+        } else {
+            manifest
+                .set_len(complete_length as u64)
+                .map_err(BackupError::Io)?;
+        }
+
+        Ok(Self {
+            target: BackupTarget::Directory {
+                directory: path.to_path_buf(),
+                manifest: Mutex::new(manifest),
+            },
+            files: Mutex::new(files),
+        })
+    }
+
+    fn open_tar_archive(path: &Path, is_resuming: bool) -> Result<Self, BackupError> {
+        let (file, manifest) = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(_) if !is_resuming => return Err(BackupError::AlreadyExists(path.to_path_buf())),
+            Ok(mut file) => {
+                // New files are appended after the last complete file of the archive
+                let (manifest, complete_length) = read_tar_manifest(&file)?;
+                file.set_len(complete_length)?;
+                file.seek(SeekFrom::End(0))?;
+                (file, manifest)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (File::create(path)?, None),
+            Err(err) => return Err(BackupError::Io(err)),
+        };
+
+        let files = manifest
+            .as_deref()
+            .map(parse_manifest)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| (entry.original_path.clone(), entry))
+            .collect();
+        let backup = Self {
+            target: BackupTarget::Tar(Mutex::new(tar::Builder::new(file))),
+            files: Mutex::new(files),
+        };
+        if manifest.is_none() {
+            backup.write_manifest(&format!("{MANIFEST_HEADER}\n"))?;
+        }
+        Ok(backup)
+    }
+
+    /// Copies the region file along with its external chunk files
+    pub fn save_region(&self, region_file_path: &Path, region: &Region) -> std::io::Result<()> {
+        self.save_file(region_file_path)?;
+        for external_file in region.get_external_files() {
+            self.save_file(external_file)?;
+        }
+        Ok(())
+    }
+
+    /// Copies the file before it gets modified or deleted, the copy is synced to the disk before
+    /// returning. Files that do not exist yet are only recorded, so that restoring the backup
+    /// deletes them.
+    pub fn save_file(&self, path: &Path) -> std::io::Result<()> {
+        let original_path = std::path::absolute(path)?;
+        if self.files.lock().unwrap().contains_key(&original_path) {
+            return Ok(());
+        }
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => Some(bytes),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        let backup_path = bytes.as_ref().map(|_| get_backup_path(&original_path));
+        match (&self.target, &backup_path) {
+            (BackupTarget::Directory { directory, .. }, Some(backup_path)) => {
+                let copy_path = directory.join(backup_path);
+                if let Some(parent) = copy_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                // A copy left by an interrupted run is complete, but it is only listed in the
+                // manifest if the file was modified since, so it is simply copied again
+                write_atomically(&copy_path, bytes.as_deref().unwrap_or_default(), |_| true)?;
+            }
+            (BackupTarget::Tar(builder), Some(backup_path)) => {
+                let bytes = bytes.as_deref().unwrap_or_default();
+                let mtime = get_modification_time(path);
+                append_to_archive(&mut builder.lock().unwrap(), backup_path, bytes, mtime)?;
+            }
+            (_, None) => {}
+        }
+
+        // Listed before the file gets modified, the hash of the written file is only known once
+        // `record_file` is called
+        let entry = ManifestEntry {
+            original_sha256: bytes.as_deref().map(get_sha256),
+            path: backup_path,
+            original_path: original_path.clone(),
+            written_sha256: None,
+        };
+        self.write_manifest_entry(&entry)?;
+        self.files.lock().unwrap().insert(original_path, entry);
+        Ok(())
+    }
+
+    /// Records the region file and its external chunk files once they have been written
+    pub fn record_region(&self, region_file_path: &Path, region: &Region) -> std::io::Result<()> {
+        self.record_file(region_file_path)?;
+        for external_file in region.get_external_files() {
+            self.record_file(external_file)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the file to the manifest along with the hash of what the run wrote, so that
+    /// restoring the backup can tell whether the file was modified since
+    pub fn record_file(&self, path: &Path) -> std::io::Result<()> {
+        let original_path = std::path::absolute(path)?;
+        let written_sha256 = match std::fs::read(path) {
+            Ok(bytes) => Some(get_sha256(&bytes)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        let entry = {
+            let mut files = self.files.lock().unwrap();
+            let Some(entry) = files.get_mut(&original_path) else {
+                return Ok(());
+            };
+            entry.written_sha256 = written_sha256;
+            entry.clone()
+        };
+
+        self.write_manifest_entry(&entry)
+    }
+
+    /// A file written several times is listed several times, the last entry is the right one
+    fn write_manifest_entry(&self, entry: &ManifestEntry) -> std::io::Result<()> {
+        self.write_manifest(&format_manifest_entry(entry))
+    }
+
+    /// Appends the lines to the manifest, and syncs them to the disk
+    fn write_manifest(&self, lines: &str) -> std::io::Result<()> {
+        match &self.target {
+            BackupTarget::Directory { manifest, .. } => {
+                let mut manifest = manifest.lock().unwrap();
+                manifest.write_all(lines.as_bytes())?;
+                manifest.sync_data()
+            }
+            BackupTarget::Tar(builder) => {
+                let mut builder = builder.lock().unwrap();
+                append_to_archive(&mut builder, MANIFEST_FILE_NAME, lines.as_bytes(), 0)
+            }
+        }
+    }
+
+    /// Terminates the tar archive. An archive that is not terminated can still be restored, or
+    /// resumed by the next run.
+    pub fn finish(self) -> std::io::Result<()> {
+        let BackupTarget::Tar(builder) = self.target else {
+            return Ok(());
+        };
+        builder.into_inner().unwrap().into_inner()?.sync_all()
+    }
+}
+
+/// Saves the region, copying the files it replaces in the backup first
+pub fn save_region_with_backup(
+    backup: Option<&Backup>,
+    region_file_path: &Path,
+    region: &Region,
+    compression_scheme: CompressionScheme,
+    compression: Compression,
+    recompress: bool,
+) -> std::io::Result<()> {
+    let Some(backup) = backup else {
+        return save_region(
+            region_file_path,
+            region,
+            compression_scheme,
+            compression,
+            recompress,
+        );
+    };
+
+    backup.save_region(region_file_path, region)?;
+    // External files created by the run are listed as not existing before it, so that restoring
+    // the backup removes them
+    let is_created = |path: &&PathBuf| !region.get_external_files().contains(path);
+    let external_files = save_region_with(
+        region_file_path,
+        region,
+        compression_scheme,
+        compression,
+        recompress,
+        |external_files| {
+            let mut created_files = external_files.iter().filter(is_created);
+            created_files.try_for_each(|path| backup.save_file(path))
+        },
+    )?;
+    backup.record_region(region_file_path, region)?;
+    let mut created_files = external_files.iter().filter(is_created);
+    created_files.try_for_each(|path| backup.record_file(path))
+}
+
+/// Removes the region, copying its files in the backup first
+pub fn remove_region_with_backup(
+    backup: Option<&Backup>,
+    region_file_path: &Path,
+    region: &Region,
+) -> std::io::Result<()> {
+    if let Some(backup) = backup {
+        backup.save_region(region_file_path, region)?;
+    }
+    remove_region(region_file_path, region)?;
+    backup.map_or(Ok(()), |backup| {
+        backup.record_region(region_file_path, region)
+    })
+}
+
+/// Removes a file that is not a readable region, copying it in the backup first
+pub fn remove_file_with_backup(backup: Option<&Backup>, path: &Path) -> std::io::Result<()> {
+    if let Some(backup) = backup {
+        backup.save_file(path)?;
+    }
+    std::fs::remove_file(path)?;
+    backup.map_or(Ok(()), |backup| backup.record_file(path))
+}
+
+/// Adds the file to the archive and syncs it to the disk
+fn append_to_archive(
+    builder: &mut tar::Builder<File>,
+    path: &str,
+    bytes: &[u8],
+    mtime: u64,
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    builder.append_data(&mut header, path, bytes)?;
+    builder.get_mut().sync_data()
+}
+
+/// Reads the manifest of the tar archive, made of every `manifest.txt` file in the archive, or
+/// `None` if there is none. Also returns the length of the archive up to the end of its last
+/// complete file, which is shorter than the archive if the run writing it got interrupted.
+pub fn read_tar_manifest(file: &File) -> std::io::Result<(Option<String>, u64)> {
+    let length = file.metadata()?.len();
+    let mut manifest: Option<String> = None;
+    let mut complete_length = 0;
+    for entry in tar::Archive::new(file).entries()? {
+        let Ok(mut entry) = entry else {
+            break;
+        };
+        let end = entry.raw_file_position() + entry.size().div_ceil(512) * 512;
+        if end > length {
+            break;
+        }
+        if entry.path()?.as_os_str() == MANIFEST_FILE_NAME {
+            entry.read_to_string(manifest.get_or_insert_default())?;
+        }
+        complete_length = end;
+    }
+    Ok((manifest, complete_length))
+}
+
+pub fn is_tar_archive(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "tar")
+}
+
+pub fn get_sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn get_modification_time(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Mirrors the absolute path of the file inside the backup, so that files from different worlds
+/// do not overwrite each other
+fn get_backup_path(original_path: &Path) -> String {
+    original_path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn format_manifest_entry(entry: &ManifestEntry) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\n",
+        entry.original_sha256.as_deref().unwrap_or("-"),
+        entry.path.as_deref().map_or(String::from("-"), escape),
+        escape(&entry.original_path.to_string_lossy()),
+        entry.written_sha256.as_deref().unwrap_or("-"),
+    )
+}
+
+/// Reads the entries of the manifest, keeping the last entry of each file
+pub fn parse_manifest(content: &str) -> Vec<ManifestEntry> {
+    let mut entries = Vec::<ManifestEntry>::new();
+    let mut indices = HashMap::new();
+    let optional = |value: &str| (value != "-").then(|| unescape(value));
+
+    for line in content.lines().filter(|line| !line.starts_with('#')) {
+        let [original_sha256, path, original_path, written_sha256] =
+            line.split('\t').collect::<Vec<_>>()[..]
+        else {
+            continue;
+        };
+        let entry = ManifestEntry {
+            original_sha256: optional(original_sha256),
+            path: optional(path),
+            original_path: PathBuf::from(unescape(original_path)),
+            written_sha256: optional(written_sha256),
+        };
+        match indices.get(&entry.original_path) {
+            Some(&index) => entries[index] = entry,
+            None => {
+                indices.insert(entry.original_path.clone(), entries.len());
+                entries.push(entry);
+            }
+        }
+    }
+
+    entries
+}
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("the backup `{0}` already exists, choose another path")]
+    AlreadyExists(PathBuf),
+    #[error("cannot create the backup: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::tag::Tag;
+    use crate::region_loader::chunk_loader::chunk::Chunk;
+    use crate::region_loader::location::Location;
+    use crate::region_loader::region_position::RegionPosition;
+
+    #[test]
+    fn test_backup_directory() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let directory = temp_dir.path().to_path_buf();
+        let world_file = directory.join("world/region/r.0.0.mca");
+        let new_file = directory.join("world/region/r.1.0.mca");
+        std::fs::create_dir_all(world_file.parent().unwrap()).unwrap();
+        std::fs::write(&world_file, b"region").unwrap();
+
+        let backup_directory = directory.join("backup");
+        let backup = Backup::create(&backup_directory, false).unwrap();
+        backup.save_file(&world_file).unwrap();
+        std::fs::write(&world_file, b"trimmed").unwrap();
+        backup.record_file(&world_file).unwrap();
+        // Only the first version of the file is kept
+        backup.save_file(&world_file).unwrap();
+        std::fs::write(&world_file, b"moved").unwrap();
+        backup.record_file(&world_file).unwrap();
+        backup.save_file(&new_file).unwrap();
+        std::fs::write(&new_file, b"moved").unwrap();
+        backup.record_file(&new_file).unwrap();
+        backup.finish().unwrap();
+
+        let manifest = std::fs::read_to_string(backup_directory.join(MANIFEST_FILE_NAME)).unwrap();
+        let entries = parse_manifest(&manifest);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].original_path, world_file);
+        assert_eq!(
+            entries[0].original_sha256.as_deref(),
+            Some("c697d2981bf416569a16cfbcdec1542b5398f3cc77d2b905819aa99c46ecf6f6")
+        );
+        assert_eq!(entries[0].written_sha256, Some(get_sha256(b"moved")));
+        let copy = std::fs::read(backup_directory.join(entries[0].path.as_ref().unwrap())).unwrap();
+        assert_eq!(copy, b"region");
+        // The second region did not exist before the run
+        assert_eq!(entries[1].original_sha256, None);
+        assert_eq!(entries[1].path, None);
+
+        assert!(matches!(
+            Backup::create(&backup_directory, false),
+            Err(BackupError::AlreadyExists(_))
+        ));
+    }
+
+    #[test]
+    fn test_resume_backup_directory() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let world_file = temp_dir.path().join("world/region/r.0.0.mca");
+        std::fs::create_dir_all(world_file.parent().unwrap()).unwrap();
+        std::fs::write(&world_file, b"region").unwrap();
+        let backup_directory = temp_dir.path().join("backup");
+        let manifest_path = backup_directory.join(MANIFEST_FILE_NAME);
+
+        // The run is interrupted after modifying the file, before recording it
+        let backup = Backup::create(&backup_directory, false).unwrap();
+        backup.save_file(&world_file).unwrap();
+        std::fs::write(&world_file, b"trimmed").unwrap();
+        drop(backup);
+        let mut manifest = OpenOptions::new()
+            .append(true)
+            .open(&manifest_path)
+            .unwrap();
+        manifest.write_all(b"0123\tpartial").unwrap();
+
+        let backup = Backup::create(&backup_directory, true).unwrap();
+        backup.save_file(&world_file).unwrap();
+        std::fs::write(&world_file, b"trimmed again").unwrap();
+        backup.record_file(&world_file).unwrap();
+
+        let entries = parse_manifest(&std::fs::read_to_string(&manifest_path).unwrap());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].original_sha256, Some(get_sha256(b"region")));
+        assert_eq!(
+            entries[0].written_sha256,
+            Some(get_sha256(b"trimmed again"))
+        );
+        let copy = std::fs::read(backup_directory.join(entries[0].path.as_ref().unwrap())).unwrap();
+        assert_eq!(copy, b"region");
+    }
+
+    #[test]
+    fn test_resume_tar_backup() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let world_file = temp_dir.path().join("world/region/r.0.0.mca");
+        let other_file = temp_dir.path().join("world/region/r.1.0.mca");
+        std::fs::create_dir_all(world_file.parent().unwrap()).unwrap();
+        std::fs::write(&world_file, b"region").unwrap();
+        std::fs::write(&other_file, b"other region").unwrap();
+        let archive_path = temp_dir.path().join("backup.tar");
+
+        // The run is interrupted after modifying the file, without terminating the archive
+        let backup = Backup::create(&archive_path, false).unwrap();
+        backup.save_file(&world_file).unwrap();
+        std::fs::write(&world_file, b"trimmed").unwrap();
+        std::mem::forget(backup);
+        let mut archive = OpenOptions::new().append(true).open(&archive_path).unwrap();
+        archive.write_all(&[1; 700]).unwrap();
+
+        let (manifest, _) = read_tar_manifest(&File::open(&archive_path).unwrap()).unwrap();
+        let entries = parse_manifest(&manifest.unwrap());
+        assert_eq!(entries[0].original_sha256, Some(get_sha256(b"region")));
+        assert_eq!(entries[0].written_sha256, None);
+
+        assert!(matches!(
+            Backup::create(&archive_path, false),
+            Err(BackupError::AlreadyExists(_))
+        ));
+        let backup = Backup::create(&archive_path, true).unwrap();
+        backup.save_file(&world_file).unwrap();
+        backup.record_file(&world_file).unwrap();
+        backup.save_file(&other_file).unwrap();
+        std::fs::remove_file(&other_file).unwrap();
+        backup.record_file(&other_file).unwrap();
+        backup.finish().unwrap();
+
+        let (manifest, _) = read_tar_manifest(&File::open(&archive_path).unwrap()).unwrap();
+        let entries = parse_manifest(&manifest.unwrap());
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].written_sha256, Some(get_sha256(b"trimmed")));
+        assert_eq!(
+            entries[1].original_sha256,
+            Some(get_sha256(b"other region"))
+        );
+        let mut archive = tar::Archive::new(File::open(&archive_path).unwrap());
+        let copies = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().to_path_buf())
+            .filter(|path| path.as_os_str() != MANIFEST_FILE_NAME)
+            .count();
+        assert_eq!(copies, 2);
+    }
+
+    #[test]
+    fn test_created_external_file() {
+        // Random data does not compress, making the chunk larger than 255 sectors
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let noise = (0..160_000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as i64
+            })
+            .collect();
+        let nbt = Tag::Compound {
+            name: None,
+            value: Vec::from([
+                Tag::Int {
+                    name: Some(String::from("xPos")),
+                    value: 0,
+                },
+                Tag::Int {
+                    name: Some(String::from("zPos")),
+                    value: 0,
+                },
+                Tag::LongArray {
+                    name: Some(String::from("noise")),
+                    value: noise,
+                },
+            ]),
+        };
+        let location = Location::new(8192, 4096, 0).unwrap();
+        let mut region = Region::new(RegionPosition { x: 0, z: 0 });
+        let chunk = Chunk::from_nbt_bytes(&nbt.to_bytes(), location, 0).unwrap();
+        region.insert_chunk(chunk).unwrap();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let region_file_path = temp_dir.path().join("r.0.0.mca");
+        let external_file = temp_dir.path().join("c.0.0.mcc");
+        let backup = Backup::create(&temp_dir.path().join("backup"), false).unwrap();
+        let compression = Compression::fast();
+        save_region_with_backup(
+            Some(&backup),
+            &region_file_path,
+            &region,
+            CompressionScheme::Zlib,
+            compression,
+            true,
+        )
+        .unwrap();
+
+        // Both files did not exist before the run, restoring the backup removes them
+        let manifest_path = temp_dir.path().join("backup").join(MANIFEST_FILE_NAME);
+        let entries = parse_manifest(&std::fs::read_to_string(manifest_path).unwrap());
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].original_path, external_file);
+        assert_eq!(entries[1].original_sha256, None);
+        assert_eq!(
+            entries[1].written_sha256,
+            Some(get_sha256(&std::fs::read(&external_file).unwrap()))
+        );
+    }
+}
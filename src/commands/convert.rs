@@ -0,0 +1,111 @@
+use crate::commands::region_writer::{is_out_of_space, remove_region, save_region};
+use crate::commands::world_run::WorldRun;
+use crate::region_loader::chunk_loader::compression_scheme::CompressionScheme;
+use crate::region_loader::region::{ParseRegionError, Region};
+use crate::region_loader::region_format::RegionFormat;
+use crate::world::get_region_files::get_region_files;
+use crate::world::linked_regions::LinkedRegion;
+use flate2::Compression;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub fn execute_convert(
+    world_paths: &Vec<PathBuf>,
+    dimensions: &[String],
+    format: RegionFormat,
+    compression_scheme: CompressionScheme,
+    compression: Compression,
+) -> Result<(), Box<dyn Error>> {
+    let entries = get_region_files(world_paths, dimensions)?;
+    let run = WorldRun::lock(world_paths)?;
+
+    let results = run.process(
+        &entries,
+        |entry| {
+            let mut results = vec![convert_region(
+                &entry.path,
+                format,
+                compression_scheme,
+                compression,
+            )];
+            for linked_region in LinkedRegion::ALL {
+                if let Some(linked_region_file) = linked_region.get_region_file(&entry.path) {
+                    if linked_region_file.exists() {
+                        results.push(convert_region(
+                            &linked_region_file,
+                            format,
+                            compression_scheme,
+                            compression,
+                        ));
+                    }
+                }
+            }
+            results
+        },
+        |results| {
+            results.iter().any(|result| {
+                matches!(result, Err(ConvertRegionError::Write(_, err)) if is_out_of_space(err))
+            })
+        },
+    );
+
+    let mut converted_regions = 0;
+    for result in results.into_iter().flat_map(|(_, results)| results) {
+        match result {
+            Ok(true) => converted_regions += 1,
+            Ok(false) => {}
+            Err(err) => println!("{err}"),
+        }
+    }
+    println!("Converted Regions: {converted_regions}");
+
+    if run.is_aborted() {
+        return Err("aborted, there is no space left on the disk".into());
+    }
+
+    Ok(())
+}
+
+/// Rewrites the region file in the given format next to the original one, then removes the
+/// original. Returns whether the region had to be converted.
+fn convert_region(
+    region_file_path: &Path,
+    format: RegionFormat,
+    compression_scheme: CompressionScheme,
+    compression: Compression,
+) -> Result<bool, ConvertRegionError> {
+    if RegionFormat::from_file_name(region_file_path) == format {
+        return Ok(false);
+    }
+
+    let converted_file_path = region_file_path.with_extension(format.get_extension());
+    if converted_file_path.exists() {
+        return Err(ConvertRegionError::AlreadyExists(converted_file_path));
+    }
+
+    let region = Region::from_file_name(region_file_path)
+        .map_err(|err| ConvertRegionError::Read(region_file_path.to_path_buf(), err))?;
+    save_region(
+        &converted_file_path,
+        &region,
+        compression_scheme,
+        compression,
+        false,
+    )
+    .map_err(|err| ConvertRegionError::Write(converted_file_path.clone(), err))?;
+    remove_region(region_file_path, &region)
+        .map_err(|err| ConvertRegionError::Write(region_file_path.to_path_buf(), err))?;
+
+    Ok(true)
+}
+
+#[derive(Error, Debug)]
+pub enum ConvertRegionError {
+    #[error("cannot convert `{0}`, the file already exists")]
+    AlreadyExists(PathBuf),
+    #[error("cannot read `{0}`: {1}")]
+    Read(PathBuf, ParseRegionError),
+    #[error("cannot write `{0}`: {1}")]
+    Write(PathBuf, std::io::Error),
+}
@@ -0,0 +1,82 @@
+use crate::region_loader::region::Region;
+use crate::region_loader::region_position::RegionPosition;
+use std::fmt::Display;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct CorruptedChunk {
+    pub region_file_path: PathBuf,
+    pub x: i32,
+    pub z: i32,
+    pub reason: String,
+}
+
+impl Display for CorruptedChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Corrupted chunk {}, {} in {}: {}",
+            self.x,
+            self.z,
+            self.region_file_path.display(),
+            self.reason
+        )
+    }
+}
+
+/// Lists the chunks of the region that are corrupted, and dumps their sectors in the quarantine
+/// directory if one is given so they can be recovered by hand
+pub fn quarantine_corrupted_chunks(
+    region_file_path: &Path,
+    region: &Region,
+    quarantine_directory: Option<&Path>,
+) -> std::io::Result<Vec<CorruptedChunk>> {
+    // Without a valid file name, coordinates are relative to the region
+    let region_position = RegionPosition::from_file_name(region_file_path).unwrap_or_default();
+    let mut corrupted_chunks = Vec::new();
+
+    for raw_chunk in region.get_raw_chunks() {
+        if !raw_chunk.has_known_compression_scheme() {
+            continue;
+        }
+
+        let (x, z) = region_position.get_chunk_position(raw_chunk.index);
+        let reason = if raw_chunk.is_lost() {
+            format!("{}, the chunk is lost", raw_chunk.reason)
+        } else {
+            raw_chunk.reason.clone()
+        };
+
+        // Sectors dropped by the header repair are dumped too, even though the chunk is lost
+        let bytes = raw_chunk.get_read_bytes();
+        if let (Some(quarantine_directory), false) = (quarantine_directory, bytes.is_empty()) {
+            let directory = get_quarantine_directory(quarantine_directory, region_file_path);
+            std::fs::create_dir_all(&directory)?;
+            std::fs::write(directory.join(format!("c.{x}.{z}.bin")), bytes)?;
+        }
+
+        corrupted_chunks.push(CorruptedChunk {
+            region_file_path: region_file_path.to_path_buf(),
+            x,
+            z,
+            reason,
+        });
+    }
+
+    Ok(corrupted_chunks)
+}
+
+/// Mirrors the directory of the region inside the quarantine directory, so chunks from different
+/// dimensions or worlds do not overwrite each other
+fn get_quarantine_directory(quarantine_directory: &Path, region_file_path: &Path) -> PathBuf {
+    let region_directory = region_file_path
+        .parent()
+        .map(|parent| {
+            parent
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect::<PathBuf>()
+        })
+        .unwrap_or_default();
+    quarantine_directory.join(region_directory)
+}
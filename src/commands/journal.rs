@@ -0,0 +1,375 @@
+use crate::commands::corrupted_chunks::CorruptedChunk;
+use crate::commands::misplaced_chunks::MisplacedChunk;
+use crate::commands::optimize_result::OptimizeResult;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+const JOURNAL_FILE_NAME: &str = ".trimmer_journal";
+const JOURNAL_HEADER: &str = "minecraft_world_trimmer journal 2";
+
+/// A region that was already processed by an interrupted run, along with its result
+pub struct JournalEntry {
+    pub world: PathBuf,
+    pub dimension: String,
+    pub result: OptimizeResult,
+}
+
+/// Records the regions processed by the write mode in each world directory, so that an
+/// interrupted run can resume where it stopped. The header holds the settings of the run, followed
+/// by tab separated records. The records of a region are only taken into account once its
+/// `region` record is written.
+pub struct Journal {
+    files: HashMap<PathBuf, Mutex<File>>,
+    completed_regions: HashSet<PathBuf>,
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Opens the journal of each world, reading the regions completed by a previous run. A run
+    /// can only be resumed with the settings it was started with.
+    pub fn open(worlds: &[&Path], settings: &str) -> Result<Self, JournalError> {
+        let header = format!("{JOURNAL_HEADER}\nsettings\t{}\n", escape(settings));
+        let mut files = HashMap::new();
+        let mut completed_regions = HashSet::new();
+        let mut entries = Vec::new();
+
+        for &world in worlds {
+            if files.contains_key(world) {
+                continue;
+            }
+            let journal_path = world.join(JOURNAL_FILE_NAME);
+            let content = match std::fs::read_to_string(&journal_path) {
+                Ok(content) => content,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(err) => return Err(err.into()),
+            };
+            let world_entries = parse_journal(world, &content);
+            if !world_entries.is_empty() && !content.starts_with(&header) {
+                let previous_settings = content
+                    .lines()
+                    .nth(1)
+                    .and_then(|line| line.strip_prefix("settings\t"))
+                    .map(unescape)
+                    .unwrap_or_default();
+                return Err(JournalError::SettingsChanged(
+                    journal_path,
+                    previous_settings,
+                ));
+            }
+            for (region_file_path, entry) in world_entries {
+                completed_regions.insert(region_file_path);
+                entries.push(entry);
+            }
+
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&journal_path)?;
+            if !content.starts_with(&header) {
+                // An empty or unknown journal is started over
+                file.set_len(0)?;
+                file.write_all(header.as_bytes())?;
+            } else {
+                // Drop the record that was being written when the run got interrupted
+                file.set_len(get_complete_length(&content) as u64)?;
+            }
+            files.insert(world.to_path_buf(), Mutex::new(file));
+        }
+
+        Ok(Self {
+            files,
+            completed_regions,
+            entries,
+        })
+    }
+
+    /// Returns the number of regions completed by a previous run
+    pub fn get_entry_count(&self) -> usize {
+        self.completed_regions.len()
+    }
+
+    pub fn contains(&self, region_file_path: &Path) -> bool {
+        self.completed_regions.contains(region_file_path)
+    }
+
+    /// Takes the results of the regions completed by a previous run
+    pub fn take_entries(&mut self) -> Vec<JournalEntry> {
+        std::mem::take(&mut self.entries)
+    }
+
+    /// Appends the result of a region to the journal of its world, and syncs it to the disk
+    pub fn record(
+        &self,
+        world: &Path,
+        region_file_path: &Path,
+        dimension: &str,
+        result: &OptimizeResult,
+    ) -> std::io::Result<()> {
+        let Some(file) = self.files.get(world) else {
+            return Ok(());
+        };
+        let relative_path = region_file_path
+            .strip_prefix(world)
+            .unwrap_or(region_file_path);
+        let record = format_records(relative_path, dimension, result);
+
+        let mut file = file.lock().unwrap();
+        file.write_all(record.as_bytes())?;
+        file.sync_data()
+    }
+
+    /// Deletes the journals once the run is complete
+    pub fn remove(self) -> std::io::Result<()> {
+        for world in self.files.into_keys() {
+            std::fs::remove_file(world.join(JOURNAL_FILE_NAME))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum JournalError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("the interrupted run recorded in `{0}` used other settings ({1}), run again with the same settings to resume it, or delete the journal to start over")]
+    SettingsChanged(PathBuf, String),
+}
+
+fn format_records(relative_path: &Path, dimension: &str, result: &OptimizeResult) -> String {
+    let mut records = String::new();
+    for unreadable_region in &result.unreadable_regions {
+        records += &format!("unreadable\t{}\n", escape(unreadable_region));
+    }
+    for corrupted_chunk in &result.corrupted_chunks {
+        records += &format!(
+            "corrupted\t{}\t{}\t{}\n",
+            corrupted_chunk.x,
+            corrupted_chunk.z,
+            escape(&corrupted_chunk.reason)
+        );
+    }
+    for misplaced_chunk in &result.misplaced_chunks {
+        // The target region is always in the same directory
+        let target_file_name = misplaced_chunk
+            .target_region_file_path
+            .as_deref()
+            .and_then(|target| target.file_name())
+            .map(|file_name| file_name.to_string_lossy())
+            .unwrap_or_default();
+        records += &format!(
+            "misplaced\t{}\t{}\t{}\n",
+            misplaced_chunk.x,
+            misplaced_chunk.z,
+            escape(&target_file_name)
+        );
+    }
+    records += &format!(
+        "region\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+        escape(&relative_path.to_string_lossy()),
+        escape(dimension),
+        result.total_chunks,
+        result.deleted_chunks,
+        result.deleted_regions,
+        result.repaired_regions,
+        result.unsupported_chunks,
+        result.entities.deleted_chunks,
+        result.entities.deleted_regions,
+        result.poi.deleted_chunks,
+        result.poi.deleted_regions,
+    );
+    records
+}
+
+/// Reads the completed regions of the journal, ignoring the records of a region that was being
+/// processed when the run got interrupted
+fn parse_journal(world: &Path, content: &str) -> Vec<(PathBuf, JournalEntry)> {
+    let mut entries = Vec::new();
+    if !content.starts_with(JOURNAL_HEADER) {
+        return entries;
+    }
+
+    let mut pending = OptimizeResult::default();
+    for line in content[..get_complete_length(content)].lines().skip(1) {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        match fields[..] {
+            ["unreadable", message] => pending.unreadable_regions.push(unescape(message)),
+            ["corrupted", x, z, reason] => {
+                let (Ok(x), Ok(z)) = (x.parse(), z.parse()) else {
+                    continue;
+                };
+                pending.corrupted_chunks.push(CorruptedChunk {
+                    region_file_path: PathBuf::new(),
+                    x,
+                    z,
+                    reason: unescape(reason),
+                });
+            }
+            ["misplaced", x, z, target_file_name] => {
+                let (Ok(x), Ok(z)) = (x.parse(), z.parse()) else {
+                    continue;
+                };
+                pending.misplaced_chunks.push(MisplacedChunk {
+                    region_file_path: PathBuf::new(),
+                    x,
+                    z,
+                    target_region_file_path: (!target_file_name.is_empty())
+                        .then(|| PathBuf::from(unescape(target_file_name))),
+                });
+            }
+            ["region", relative_path, dimension, ref counts @ ..] => {
+                let mut result = std::mem::take(&mut pending);
+                let Some(counts) = parse_counts(counts) else {
+                    continue;
+                };
+                [
+                    result.total_chunks,
+                    result.deleted_chunks,
+                    result.deleted_regions,
+                    result.repaired_regions,
+                    result.unsupported_chunks,
+                    result.entities.deleted_chunks,
+                    result.entities.deleted_regions,
+                    result.poi.deleted_chunks,
+                    result.poi.deleted_regions,
+                ] = counts;
+
+                let region_file_path = world.join(unescape(relative_path));
+                for corrupted_chunk in &mut result.corrupted_chunks {
+                    corrupted_chunk.region_file_path = region_file_path.clone();
+                }
+                for misplaced_chunk in &mut result.misplaced_chunks {
+                    misplaced_chunk.region_file_path = region_file_path.clone();
+                    misplaced_chunk.target_region_file_path = misplaced_chunk
+                        .target_region_file_path
+                        .take()
+                        .map(|file_name| region_file_path.with_file_name(file_name));
+                }
+
+                let dimension = unescape(dimension);
+                entries.push((
+                    region_file_path,
+                    JournalEntry {
+                        world: world.to_path_buf(),
+                        dimension,
+                        result,
+                    },
+                ));
+            }
+            // Unknown records are skipped along with the region they belong to
+            _ => pending = OptimizeResult::default(),
+        }
+    }
+
+    entries
+}
+
+/// A record without its line break has not been fully written
+fn get_complete_length(content: &str) -> usize {
+    content.rfind('\n').map_or(0, |end| end + 1)
+}
+
+fn parse_counts(counts: &[&str]) -> Option<[usize; 9]> {
+    let counts = counts
+        .iter()
+        .map(|count| count.parse().ok())
+        .collect::<Option<Vec<_>>>()?;
+    counts.try_into().ok()
+}
+
+/// Escapes the characters used as separators by the journal
+pub fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+pub fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_journal() {
+        let world = Path::new("world");
+        let region_file_path = world.join("region/r.0.0.mca");
+        let result = OptimizeResult {
+            total_chunks: 10,
+            deleted_chunks: 4,
+            unreadable_regions: vec![String::from("Cannot read\tsomething")],
+            misplaced_chunks: vec![MisplacedChunk {
+                region_file_path: region_file_path.clone(),
+                x: 40,
+                z: 2,
+                target_region_file_path: Some(world.join("region/r.1.0.mca")),
+            }],
+            ..Default::default()
+        };
+
+        let mut content = format!("{JOURNAL_HEADER}\n");
+        content += &format_records(
+            Path::new("region/r.0.0.mca"),
+            "minecraft:overworld",
+            &result,
+        );
+        // The run got interrupted while writing the records of another region
+        content += "unreadable\tCannot read\nregion\tregion/r.1.0.mca\tminecraft:over";
+
+        let entries = parse_journal(world, &content);
+        assert_eq!(entries.len(), 1);
+        let (path, entry) = &entries[0];
+        assert_eq!(*path, region_file_path);
+        assert_eq!(entry.world, world);
+        assert_eq!(entry.dimension, "minecraft:overworld");
+        assert_eq!(entry.result.total_chunks, 10);
+        assert_eq!(entry.result.deleted_chunks, 4);
+        assert_eq!(entry.result.unreadable_regions, result.unreadable_regions);
+        let misplaced_chunk = &entry.result.misplaced_chunks[0];
+        assert_eq!(misplaced_chunk.region_file_path, region_file_path);
+        assert_eq!(
+            misplaced_chunk.target_region_file_path,
+            Some(world.join("region/r.1.0.mca"))
+        );
+    }
+
+    #[test]
+    fn test_settings_changed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let world = temp_dir.path();
+        let result = OptimizeResult::default();
+
+        let journal = Journal::open(&[world], "min-inhabited-time 5m").unwrap();
+        journal
+            .record(world, &world.join("region/r.0.0.mca"), "overworld", &result)
+            .unwrap();
+        drop(journal);
+
+        assert!(matches!(
+            Journal::open(&[world], "min-inhabited-time 0"),
+            Err(JournalError::SettingsChanged(_, settings)) if settings == "min-inhabited-time 5m"
+        ));
+        let journal = Journal::open(&[world], "min-inhabited-time 5m").unwrap();
+        assert_eq!(journal.get_entry_count(), 1);
+    }
+}
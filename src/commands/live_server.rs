@@ -0,0 +1,300 @@
+use crate::nbt::binary_reader::BinaryReader;
+use crate::nbt::parse::parse_tag;
+use crate::nbt::tag::Tag;
+use crate::rcon::{RconClient, RconError};
+use crate::region_loader::region_position::RegionPosition;
+use crate::world::dimension::get_dimensions;
+use flate2::read::GzDecoder;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Saving a large world can take a while
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(600);
+/// Players keep moving during the run, so their positions are queried again once they are older
+/// than this
+const LOADED_REGIONS_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Largest radius around players and the spawn, 1024 chunks is already 65 by 65 regions per player
+pub const MAX_LOADED_RADIUS: u32 = 1024;
+
+/// How to reach the RCON server of a running server
+pub struct RconOptions<'a> {
+    pub address: &'a str,
+    pub password: &'a str,
+    /// Regions within this many chunks of a player or of the spawn are considered loaded
+    pub loaded_radius: u32,
+}
+
+/// A running server whose saves are turned off until this is dropped, so that the game does not
+/// write region files while they are trimmed
+pub struct LiveServer {
+    client: RconClient,
+    world_paths: Vec<PathBuf>,
+    loaded_radius: u32,
+    loaded_regions: LoadedRegions,
+    queried_at: Instant,
+}
+
+impl LiveServer {
+    /// Turns off the saves of the server, then waits for it to write every loaded chunk
+    pub fn start(options: &RconOptions, world_paths: &[PathBuf]) -> Result<Self, LiveServerError> {
+        if options.loaded_radius > MAX_LOADED_RADIUS {
+            return Err(LiveServerError::RadiusTooLarge(options.loaded_radius));
+        }
+        let client = RconClient::connect(options.address, options.password, COMMAND_TIMEOUT)?;
+        let mut server = Self {
+            client,
+            world_paths: world_paths.to_vec(),
+            loaded_radius: options.loaded_radius,
+            loaded_regions: LoadedRegions::default(),
+            queried_at: Instant::now(),
+        };
+        server.client.command("save-off")?;
+        // The server only answers once the chunks are written
+        server.client.command("save-all flush")?;
+        server.refresh_loaded_regions()?;
+        Ok(server)
+    }
+
+    /// Whether the server may be holding the region file in memory, in which case it must be left
+    /// untouched. The positions of the players are queried again when they are outdated.
+    pub fn is_loaded(
+        &mut self,
+        dimension: &str,
+        region_file_path: &Path,
+    ) -> Result<bool, LiveServerError> {
+        if self.queried_at.elapsed() >= LOADED_REGIONS_REFRESH_INTERVAL {
+            self.refresh_loaded_regions()?;
+        }
+        Ok(self.loaded_regions.contains(dimension, region_file_path))
+    }
+
+    /// Finds the regions around the players and the spawn of each world
+    fn refresh_loaded_regions(&mut self) -> Result<(), LiveServerError> {
+        let dimension_ids = self
+            .world_paths
+            .iter()
+            .flat_map(|world_path| get_dimensions(world_path))
+            .map(|dimension| dimension.id)
+            .collect::<HashSet<_>>();
+        let radius = self.loaded_radius;
+        let mut loaded_regions = LoadedRegions::default();
+
+        for world_path in &self.world_paths {
+            let (x, z) = get_spawn(world_path).unwrap_or((0, 0));
+            loaded_regions.insert("minecraft:overworld", x >> 4, z >> 4, radius);
+        }
+
+        let players = parse_player_names(&self.client.command("list")?);
+        for player in players {
+            let position = self
+                .client
+                .command(&format!("data get entity {player} Pos"))?;
+            let (x, z) = parse_position(&position)
+                .ok_or_else(|| LiveServerError::UnknownPosition(player.clone(), position))?;
+            let dimension = self
+                .client
+                .command(&format!("data get entity {player} Dimension"))?;
+            let dimension = parse_entity_data(&dimension)
+                .map(|dimension| dimension.trim_matches('"').to_string())
+                .ok_or_else(|| LiveServerError::UnknownPosition(player.clone(), dimension))?;
+
+            let (chunk_x, chunk_z) = (x.floor() as i32 >> 4, z.floor() as i32 >> 4);
+            if dimension_ids.contains(&dimension) {
+                loaded_regions.insert(&dimension, chunk_x, chunk_z, radius);
+            } else {
+                // Worlds of plugins may not use the dimension id of their directory
+                for dimension in &dimension_ids {
+                    loaded_regions.insert(dimension, chunk_x, chunk_z, radius);
+                }
+            }
+        }
+
+        self.loaded_regions = loaded_regions;
+        self.queried_at = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for LiveServer {
+    fn drop(&mut self) {
+        if let Err(err) = self.client.command("save-on") {
+            eprintln!("Cannot turn the saves back on, run `save-on` on the server: {err}");
+        }
+    }
+}
+
+/// The regions of each dimension that a running server may be holding in memory
+#[derive(Default)]
+struct LoadedRegions {
+    regions: HashSet<(String, RegionPosition)>,
+}
+
+impl LoadedRegions {
+    /// Adds the regions within `radius` chunks of the chunk
+    fn insert(&mut self, dimension: &str, chunk_x: i32, chunk_z: i32, radius: u32) {
+        let get_region =
+            |chunk: i32, offset: i64| (i64::from(chunk) + offset).div_euclid(32) as i32;
+        let radius = i64::from(radius);
+        let min = RegionPosition {
+            x: get_region(chunk_x, -radius),
+            z: get_region(chunk_z, -radius),
+        };
+        let max = RegionPosition {
+            x: get_region(chunk_x, radius),
+            z: get_region(chunk_z, radius),
+        };
+        for x in min.x..=max.x {
+            for z in min.z..=max.z {
+                self.regions
+                    .insert((dimension.to_string(), RegionPosition { x, z }));
+            }
+        }
+    }
+
+    /// Whether the region file may be loaded, files without coordinates are always considered
+    /// loaded
+    fn contains(&self, dimension: &str, region_file_path: &Path) -> bool {
+        RegionPosition::from_file_name(region_file_path)
+            .is_none_or(|position| self.regions.contains(&(dimension.to_string(), position)))
+    }
+}
+
+/// Reads the spawn block coordinates from `level.dat`
+fn get_spawn(world_path: &Path) -> Option<(i32, i32)> {
+    let mut bytes = Vec::new();
+    GzDecoder::new(std::fs::File::open(world_path.join("level.dat")).ok()?)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    let level = parse_tag(&mut BinaryReader::new(&bytes)).ok()?;
+    let data = level.find_tag("Data")?;
+
+    if let (Some(x), Some(z)) = (data.find_tag("SpawnX"), data.find_tag("SpawnZ")) {
+        return Some((*x.get_int()?, *z.get_int()?));
+    }
+    // Since 1.21.9, the spawn is stored along with its dimension
+    match data.find_tag("spawn")?.find_tag("pos")? {
+        Tag::IntArray { value, .. } if value.len() == 3 => Some((value[0], value[2])),
+        _ => None,
+    }
+}
+
+/// Parses the output of the `list` command, such as
+/// `There are 2 of a max of 20 players online: Steve, Alex`
+fn parse_player_names(list: &str) -> Vec<String> {
+    list.rsplit_once(':')
+        .map(|(_, names)| {
+            names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parses the output of the `data get entity` command, such as
+/// `Steve has the following entity data: [12.5d, 64.0d, -3.2d]`
+fn parse_entity_data(output: &str) -> Option<&str> {
+    output
+        .split_once("entity data: ")
+        .map(|(_, data)| data.trim())
+}
+
+fn parse_position(output: &str) -> Option<(f64, f64)> {
+    let coordinates = parse_entity_data(output)?
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split(',')
+        .map(|coordinate| coordinate.trim().trim_end_matches('d').parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    match coordinates[..] {
+        [x, _, z] => Some((x, z)),
+        _ => None,
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LiveServerError {
+    #[error(transparent)]
+    Rcon(#[from] RconError),
+    #[error("cannot find the position of the player {0}: {1}")]
+    UnknownPosition(String, String),
+    #[error("the loaded radius {0} is larger than the maximum of {MAX_LOADED_RADIUS} chunks")]
+    RadiusTooLarge(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rcon::tests::start_fake_server;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn test_live_server() {
+        // Steve goes back to the overworld after the first query
+        let queries = std::sync::atomic::AtomicUsize::new(0);
+        let (address, handle) = start_fake_server("secret", move |command| match command {
+            "list" => String::from("There are 1 of a max of 20 players online: Steve"),
+            "data get entity Steve Pos" => {
+                String::from("Steve has the following entity data: [1030.5d, 64.0d, -3.2d]")
+            }
+            "data get entity Steve Dimension" => {
+                let dimension = match queries.fetch_add(1, Ordering::Relaxed) {
+                    0 => "minecraft:the_nether",
+                    _ => "minecraft:overworld",
+                };
+                format!("Steve has the following entity data: \"{dimension}\"")
+            }
+            _ => String::new(),
+        });
+        let options = RconOptions {
+            address: &address,
+            password: "secret",
+            loaded_radius: 8,
+        };
+
+        let mut server = LiveServer::start(&options, &[PathBuf::from("missing_world")]).unwrap();
+        let is_loaded = |dimension, file_name| {
+            server
+                .loaded_regions
+                .contains(dimension, Path::new(file_name))
+        };
+        let nether = "minecraft:the_nether";
+        assert!(is_loaded(nether, "r.2.-1.mca"));
+        assert!(is_loaded(nether, "r.1.0.mca"));
+        assert!(!is_loaded(nether, "r.0.0.mca"));
+        assert!(!is_loaded("minecraft:overworld", "r.2.-1.mca"));
+        // Without a level.dat, the spawn is assumed to be at 0, 0
+        assert!(is_loaded("minecraft:overworld", "r.-1.-1.mca"));
+
+        // Outdated positions are queried again
+        server.queried_at -= LOADED_REGIONS_REFRESH_INTERVAL;
+        assert!(server
+            .is_loaded("minecraft:overworld", Path::new("r.2.-1.mca"))
+            .unwrap());
+        assert!(!server.is_loaded(nether, Path::new("r.2.-1.mca")).unwrap());
+        drop(server);
+
+        let commands = handle.join().unwrap();
+        assert_eq!(commands[..2], ["save-off", "save-all flush"]);
+        assert_eq!(commands.last().unwrap(), "save-on");
+    }
+
+    #[test]
+    fn test_loaded_radius_too_large() {
+        let options = RconOptions {
+            address: "127.0.0.1:0",
+            password: "secret",
+            loaded_radius: MAX_LOADED_RADIUS + 1,
+        };
+        assert!(matches!(
+            LiveServer::start(&options, &[]),
+            Err(LiveServerError::RadiusTooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::commands::backup::{remove_region_with_backup, save_region_with_backup, Backup};
+use crate::region_loader::chunk_loader::compression_scheme::CompressionScheme;
+use crate::region_loader::region::{get_slot, Region};
+use crate::region_loader::region_position::RegionPosition;
+use flate2::Compression;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+/// A chunk whose coordinates do not match the region file or the slot it was read from
+#[derive(Clone, Debug)]
+pub struct MisplacedChunk {
+    pub region_file_path: PathBuf,
+    pub x: i32,
+    pub z: i32,
+    /// The region file the chunk belongs to, `None` if it is in the right region but the wrong
+    /// slot
+    pub target_region_file_path: Option<PathBuf>,
+}
+
+impl Display for MisplacedChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.target_region_file_path {
+            Some(target) => write!(
+                f,
+                "Misplaced chunk {}, {} in {}: belongs to {}",
+                self.x,
+                self.z,
+                self.region_file_path.display(),
+                target.display()
+            ),
+            None => write!(
+                f,
+                "Misplaced chunk {}, {} in {}: stored in the wrong slot",
+                self.x,
+                self.z,
+                self.region_file_path.display()
+            ),
+        }
+    }
+}
+
+/// Lists the chunks of the region whose coordinates do not match the region file name or the slot
+/// they were read from
+pub fn find_misplaced_chunks(region_file_path: &Path, region: &Region) -> Vec<MisplacedChunk> {
+    let Some(region_position) = RegionPosition::from_file_name(region_file_path) else {
+        return Vec::new();
+    };
+    let extension = region_file_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("mca");
+
+    region
+        .get_chunks()
+        .iter()
+        .filter_map(|chunk| {
+            let (x, z) = chunk.get_position().ok()?;
+            let target_position = RegionPosition {
+                x: x.div_euclid(32),
+                z: z.div_euclid(32),
+            };
+            let target_region_file_path = if target_position != region_position {
+                let file_name =
+                    format!("r.{}.{}.{extension}", target_position.x, target_position.z);
+                Some(region_file_path.with_file_name(file_name))
+            } else if get_slot(chunk) != chunk.index {
+                None
+            } else {
+                return None;
+            };
+
+            Some(MisplacedChunk {
+                region_file_path: region_file_path.to_path_buf(),
+                x,
+                z,
+                target_region_file_path,
+            })
+        })
+        .collect()
+}
+
+/// Moves each misplaced chunk into the region file it belongs to, creating it if needed.
+/// Regions are written one at a time, as several chunks may be moved to the same region.
+/// Chunks are kept where they are if their slot in the target region is already used.
+/// Returns the number of moved chunks along with the chunks that could not be moved.
+pub fn move_misplaced_chunks(
+    misplaced_chunks: &[MisplacedChunk],
+    compression_scheme: CompressionScheme,
+    compression: Compression,
+    recompress: bool,
+    backup: Option<&Backup>,
+) -> std::io::Result<(usize, Vec<MisplacedChunk>)> {
+    let mut chunks_by_region = BTreeMap::<_, Vec<_>>::new();
+    for misplaced_chunk in misplaced_chunks {
+        if misplaced_chunk.target_region_file_path.is_some() {
+            chunks_by_region
+                .entry(&misplaced_chunk.region_file_path)
+                .or_default()
+                .push(misplaced_chunk);
+        }
+    }
+
+    let mut moved_chunks = 0;
+    let mut conflicts = Vec::new();
+    for (region_file_path, misplaced_chunks) in chunks_by_region {
+        let Ok(mut region) = Region::from_file_name(region_file_path) else {
+            continue;
+        };
+
+        for misplaced_chunk in misplaced_chunks {
+            let Some(target_region_file_path) = &misplaced_chunk.target_region_file_path else {
+                continue;
+            };
+            let (x, z) = (misplaced_chunk.x, misplaced_chunk.z);
+            let Some(chunk) = region
+                .get_chunks()
+                .iter()
+                .find(|chunk| chunk.get_position() == Ok((x, z)))
+                .cloned()
+            else {
+                continue;
+            };
+
+            let mut target_region = if target_region_file_path.exists() {
+                match Region::from_file_name(target_region_file_path) {
+                    Ok(target_region) => target_region,
+                    Err(_) => {
+                        // Never overwrite a region that cannot be read
+                        conflicts.push(misplaced_chunk.clone());
+                        continue;
+                    }
+                }
+            } else {
+                let position = RegionPosition::from_file_name(target_region_file_path);
+                Region::new(position.unwrap_or_default())
+            };
+
+            if target_region.insert_chunk(chunk).is_err() {
+                conflicts.push(misplaced_chunk.clone());
+                continue;
+            }
+            // The target region is written before the chunk is removed from this one
+            save_region_with_backup(
+                backup,
+                target_region_file_path,
+                &target_region,
+                compression_scheme,
+                compression,
+                recompress,
+            )?;
+            region.take_chunk(x, z);
+            moved_chunks += 1;
+        }
+
+        if region.is_empty() {
+            remove_region_with_backup(backup, region_file_path, &region)?;
+        } else if region.is_modified() {
+            save_region_with_backup(
+                backup,
+                region_file_path,
+                &region,
+                compression_scheme,
+                compression,
+                recompress,
+            )?;
+        }
+    }
+
+    Ok((moved_chunks, conflicts))
+}
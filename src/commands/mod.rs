@@ -1,3 +1,20 @@
+mod backup;
+mod convert;
+mod corrupted_chunks;
+mod journal;
+mod live_server;
+mod misplaced_chunks;
 mod optimize_result;
-pub mod read;
-pub mod write;
+mod read;
+mod recover;
+mod region_writer;
+mod restore;
+mod world_run;
+mod write;
+
+pub use convert::execute_convert;
+pub use live_server::{RconOptions, MAX_LOADED_RADIUS};
+pub use read::execute_read;
+pub use recover::execute_recover;
+pub use restore::{execute_restore, RestoreFilter};
+pub use write::{execute_write, WriteOptions};
@@ -1,10 +1,85 @@
+use crate::commands::corrupted_chunks::CorruptedChunk;
+use crate::commands::misplaced_chunks::MisplacedChunk;
+use crate::region_loader::region::Region;
+use crate::trim_policy::Ticks;
+use crate::world::get_region_files::RegionFile;
+use crate::world::linked_regions::LinkedRegion;
 use std::fmt::Display;
+use std::path::PathBuf;
+
+/// The minimum inhabited times for which the check mode counts the deleted chunks: 1s, 10s, 30s,
+/// 1m, 5m, 15m and 1h
+pub const MIN_INHABITED_TIME_CANDIDATES: [Ticks; 7] = [
+    Ticks(20),
+    Ticks(200),
+    Ticks(600),
+    Ticks(1200),
+    Ticks(6000),
+    Ticks(18000),
+    Ticks(72000),
+];
+
+/// A dimension of one of the worlds, results are reported for each of them as several worlds can
+/// have the same dimensions
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WorldDimension {
+    pub world: PathBuf,
+    pub dimension: String,
+}
+
+impl From<&RegionFile> for WorldDimension {
+    fn from(region_file: &RegionFile) -> Self {
+        Self {
+            world: region_file.world.clone(),
+            dimension: region_file.dimension.clone(),
+        }
+    }
+}
+
+impl Display for WorldDimension {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} of {}", self.dimension, self.world.display())
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct LinkedRegionResult {
+    pub deleted_chunks: usize,
+    pub deleted_regions: usize,
+}
 
 #[derive(Default, Clone)]
 pub struct OptimizeResult {
     pub total_chunks: usize,
     pub deleted_chunks: usize,
     pub deleted_regions: usize,
+    /// Regions whose location table had invalid entries and was rebuilt
+    pub repaired_regions: usize,
+    /// Regions that cannot be read, they are left untouched
+    pub unreadable_regions: Vec<String>,
+    /// Chunks compressed with an unsupported algorithm, they are always kept
+    pub unsupported_chunks: usize,
+    /// Chunks that cannot be read, they are kept in their original slot
+    pub corrupted_chunks: Vec<CorruptedChunk>,
+    /// Kept chunks whose coordinates do not match their region file or slot
+    pub misplaced_chunks: Vec<MisplacedChunk>,
+    pub entities: LinkedRegionResult,
+    pub poi: LinkedRegionResult,
+    /// The chunks that would be deleted with each of the `MIN_INHABITED_TIME_CANDIDATES`, only
+    /// counted by the check mode
+    pub deleted_chunks_by_min_inhabited_time: Vec<usize>,
+}
+
+impl OptimizeResult {
+    pub fn get_linked_region_result(
+        &mut self,
+        linked_region: LinkedRegion,
+    ) -> &mut LinkedRegionResult {
+        match linked_region {
+            LinkedRegion::Entities => &mut self.entities,
+            LinkedRegion::Poi => &mut self.poi,
+        }
+    }
 }
 
 impl Display for OptimizeResult {
@@ -14,21 +89,168 @@ impl Display for OptimizeResult {
             "Optimization Result:\n\
                    Total Chunks: {}\n\
                    Deleted Chunks: {}\n\
-                   Deleted Regions: {}",
-            self.total_chunks, self.deleted_chunks, self.deleted_regions
-        )
+                   Deleted Regions: {}\n\
+                   Repaired Regions: {}\n\
+                   Unreadable Regions: {}\n\
+                   Unsupported Chunks: {}\n\
+                   Corrupted Chunks: {}\n\
+                   Misplaced Chunks: {}\n\
+                   Deleted Entity Chunks: {}\n\
+                   Deleted Entity Regions: {}\n\
+                   Deleted POI Chunks: {}\n\
+                   Deleted POI Regions: {}",
+            self.total_chunks,
+            self.deleted_chunks,
+            self.deleted_regions,
+            self.repaired_regions,
+            self.unreadable_regions.len(),
+            self.unsupported_chunks,
+            self.corrupted_chunks.len(),
+            self.misplaced_chunks.len(),
+            self.entities.deleted_chunks,
+            self.entities.deleted_regions,
+            self.poi.deleted_chunks,
+            self.poi.deleted_regions
+        )?;
+
+        if !self.deleted_chunks_by_min_inhabited_time.is_empty() {
+            write!(f, "\nDeleted Chunks by Minimum Inhabited Time:")?;
+            for (min_inhabited_time, deleted_chunks) in MIN_INHABITED_TIME_CANDIDATES
+                .iter()
+                .zip(&self.deleted_chunks_by_min_inhabited_time)
+            {
+                write!(f, "\n  {min_inhabited_time}: {deleted_chunks}")?;
+            }
+        }
+        Ok(())
     }
 }
 
+/// Counts the chunks compressed with an algorithm this tool does not know
+pub fn count_unsupported_chunks(region: &Region) -> usize {
+    region
+        .get_raw_chunks()
+        .iter()
+        .filter(|chunk| !chunk.has_known_compression_scheme())
+        .count()
+}
+
 pub fn reduce_optimize_results(results: &mut [OptimizeResult]) -> OptimizeResult {
     results
         .iter_mut()
         .reduce(|acc, cur| {
             acc.deleted_regions += cur.deleted_regions;
+            acc.repaired_regions += cur.repaired_regions;
+            acc.unreadable_regions.append(&mut cur.unreadable_regions);
             acc.total_chunks += cur.total_chunks;
             acc.deleted_chunks += cur.deleted_chunks;
+            acc.unsupported_chunks += cur.unsupported_chunks;
+            acc.corrupted_chunks.append(&mut cur.corrupted_chunks);
+            acc.misplaced_chunks.append(&mut cur.misplaced_chunks);
+            for linked_region in LinkedRegion::ALL {
+                let cur = cur.get_linked_region_result(linked_region).clone();
+                let acc = acc.get_linked_region_result(linked_region);
+                acc.deleted_chunks += cur.deleted_chunks;
+                acc.deleted_regions += cur.deleted_regions;
+            }
+            if acc.deleted_chunks_by_min_inhabited_time.is_empty() {
+                acc.deleted_chunks_by_min_inhabited_time =
+                    vec![0; cur.deleted_chunks_by_min_inhabited_time.len()];
+            }
+            for (acc, cur) in acc
+                .deleted_chunks_by_min_inhabited_time
+                .iter_mut()
+                .zip(&cur.deleted_chunks_by_min_inhabited_time)
+            {
+                *acc += cur;
+            }
             acc
         })
         .cloned()
         .unwrap_or_default()
 }
+
+/// Merges the results of each dimension of each world, keeping the order in which they were found
+pub fn reduce_results_by_dimension(
+    results: Vec<(WorldDimension, OptimizeResult)>,
+) -> Vec<(WorldDimension, OptimizeResult)> {
+    let mut dimensions: Vec<(WorldDimension, Vec<OptimizeResult>)> = Vec::new();
+    for (dimension, result) in results {
+        match dimensions.iter_mut().find(|(key, _)| *key == dimension) {
+            Some((_, dimension_results)) => dimension_results.push(result),
+            None => dimensions.push((dimension, vec![result])),
+        }
+    }
+
+    dimensions
+        .into_iter()
+        .map(|(dimension, mut results)| (dimension, reduce_optimize_results(&mut results)))
+        .collect()
+}
+
+/// Prints the corrupted chunks and the result of each dimension of each world, followed by the
+/// total when more than one dimension was processed
+pub fn print_results(results: Vec<(WorldDimension, OptimizeResult)>) {
+    let mut dimensions = reduce_results_by_dimension(results);
+
+    for (_, result) in &dimensions {
+        for unreadable_region in &result.unreadable_regions {
+            println!("{unreadable_region}");
+        }
+        for corrupted_chunk in &result.corrupted_chunks {
+            println!("{corrupted_chunk}");
+        }
+        for misplaced_chunk in &result.misplaced_chunks {
+            println!("{misplaced_chunk}");
+        }
+    }
+    for (dimension, result) in &dimensions {
+        println!("Dimension {dimension}\n{result}\n");
+    }
+    if dimensions.len() > 1 {
+        let mut results = dimensions
+            .iter_mut()
+            .map(|(_, result)| std::mem::take(result))
+            .collect::<Vec<_>>();
+        println!("Total\n{}", reduce_optimize_results(&mut results));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduce_results_by_dimension() {
+        let result = |deleted_chunks| OptimizeResult {
+            total_chunks: 10,
+            deleted_chunks,
+            ..Default::default()
+        };
+        let dimension = |world: &str, dimension: &str| WorldDimension {
+            world: PathBuf::from(world),
+            dimension: String::from(dimension),
+        };
+        let results = vec![
+            (dimension("world", "minecraft:overworld"), result(1)),
+            (dimension("world", "mypack:sky"), result(2)),
+            (dimension("world", "minecraft:overworld"), result(3)),
+            // Server worlds each have an overworld
+            (dimension("world_nether", "minecraft:overworld"), result(5)),
+        ];
+
+        let dimensions = reduce_results_by_dimension(results);
+
+        assert_eq!(dimensions.len(), 3);
+        assert_eq!(dimensions[0].0, dimension("world", "minecraft:overworld"));
+        assert_eq!(dimensions[0].1.total_chunks, 20);
+        assert_eq!(dimensions[0].1.deleted_chunks, 4);
+        assert_eq!(dimensions[1].0, dimension("world", "mypack:sky"));
+        assert_eq!(dimensions[1].1.deleted_chunks, 2);
+        assert_eq!(
+            dimensions[2].0,
+            dimension("world_nether", "minecraft:overworld")
+        );
+        assert_eq!(dimensions[2].1.deleted_chunks, 5);
+    }
+}
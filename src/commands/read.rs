@@ -1,14 +1,27 @@
-use crate::commands::optimize_result::{reduce_optimize_results, OptimizeResult};
-use crate::region_loader::region::Region;
+use crate::commands::corrupted_chunks::quarantine_corrupted_chunks;
+use crate::commands::misplaced_chunks::find_misplaced_chunks;
+use crate::commands::optimize_result::{
+    count_unsupported_chunks, print_results, LinkedRegionResult, OptimizeResult, WorldDimension,
+    MIN_INHABITED_TIME_CANDIDATES,
+};
+use crate::region_loader::region::{ParseRegionError, Region};
+use crate::trim_policy::TrimPolicy;
 use crate::world::get_region_files::get_region_files;
+use crate::world::linked_regions::LinkedRegion;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::iter::ParallelIterator;
 use rayon::prelude::IntoParallelRefIterator;
+use std::collections::HashSet;
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-pub fn execute_read(world_paths: &Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
-    let entries = get_region_files(world_paths)?;
+pub fn execute_read(
+    world_paths: &Vec<PathBuf>,
+    dimensions: &[String],
+    quarantine_directory: Option<&Path>,
+    policy: &TrimPolicy,
+) -> Result<(), Box<dyn Error>> {
+    let entries = get_region_files(world_paths, dimensions)?;
     let pb = ProgressBar::new(entries.len() as u64);
     let style = ProgressStyle::with_template(
         "{percent}% {bar} {pos}/{len} [{elapsed_precise}>{eta_precise}, {per_sec}]",
@@ -16,43 +29,122 @@ pub fn execute_read(world_paths: &Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
     .unwrap();
     pb.set_style(style);
 
-    let mut results = entries
+    let results = entries
         .par_iter()
         .map(|entry| {
-            let result = optimize_read(entry);
+            let result = optimize_read(
+                &entry.path,
+                quarantine_directory,
+                &policy.for_dimension(&entry.dimension),
+            );
             pb.inc(1);
-            result
+            (entry, result)
         })
-        .flatten()
-        .collect::<Vec<OptimizeResult>>();
+        .collect::<Vec<_>>();
 
-    let result = reduce_optimize_results(&mut results);
-    println!("{result}");
+    let mut optimize_results = Vec::new();
+    for (entry, result) in results {
+        match result {
+            Ok(result) => optimize_results.push((WorldDimension::from(entry), result)),
+            // Such as failing to write a corrupted chunk to the quarantine directory
+            Err(err) => println!("Cannot check {}: {err}", entry.path.display()),
+        }
+    }
+    print_results(optimize_results);
 
     Ok(())
 }
 
-fn optimize_read(region_file_path: &PathBuf) -> std::io::Result<OptimizeResult> {
+fn optimize_read(
+    region_file_path: &Path,
+    quarantine_directory: Option<&Path>,
+    policy: &TrimPolicy,
+) -> std::io::Result<OptimizeResult> {
     let mut result = OptimizeResult::default();
 
-    match Region::from_file_name(region_file_path) {
+    let deleted_indices = match Region::from_file_name(region_file_path) {
         Ok(region) => {
-            let chunks = region.get_chunks();
-            result.total_chunks += chunks.len();
-
-            for chunk in chunks {
-                if chunk.should_delete() {
-                    result.deleted_chunks += 1;
-                }
+            result.total_chunks += region.get_chunk_count();
+            result.unsupported_chunks += count_unsupported_chunks(&region);
+            if region.is_header_repaired() {
+                result.repaired_regions += 1;
             }
+            result.corrupted_chunks =
+                quarantine_corrupted_chunks(region_file_path, &region, quarantine_directory)?;
+
+            let deleted_indices = region
+                .get_chunks()
+                .iter()
+                .filter(|chunk| policy.should_delete(chunk))
+                .map(|chunk| chunk.index)
+                .collect::<HashSet<_>>();
+            result.deleted_chunks += deleted_indices.len();
+            result.deleted_chunks_by_min_inhabited_time = MIN_INHABITED_TIME_CANDIDATES
+                .iter()
+                .map(|min_inhabited_time| {
+                    region
+                        .get_chunks()
+                        .iter()
+                        .filter(|chunk| chunk.should_delete_with(*min_inhabited_time))
+                        .count()
+                })
+                .collect();
+            result.misplaced_chunks = find_misplaced_chunks(region_file_path, &region)
+                .into_iter()
+                .filter(|misplaced_chunk| {
+                    region.get_chunks().iter().any(|chunk| {
+                        !policy.should_delete(chunk)
+                            && chunk.get_position() == Ok((misplaced_chunk.x, misplaced_chunk.z))
+                    })
+                })
+                .collect();
+
             if result.deleted_chunks >= result.total_chunks {
                 result.deleted_regions += 1;
             }
+            deleted_indices
         }
-        Err(_) => {
+        Err(ParseRegionError::HeaderError) => {
+            // Too small to hold any chunk
             result.deleted_regions += 1;
+            (0..1024).collect()
+        }
+        Err(err) => {
+            result
+                .unreadable_regions
+                .push(format!("Cannot read {}: {err}", region_file_path.display()));
+            HashSet::new()
+        }
+    };
+
+    for linked_region in LinkedRegion::ALL {
+        if let Some(linked_region_file) = linked_region.get_region_file(region_file_path) {
+            *result.get_linked_region_result(linked_region) =
+                count_linked_chunks(&linked_region_file, &deleted_indices);
         }
     }
 
     Ok(result)
 }
+
+/// Counts the chunks of a linked region that would be deleted along with the terrain chunks
+fn count_linked_chunks(
+    linked_region_file: &Path,
+    deleted_indices: &HashSet<usize>,
+) -> LinkedRegionResult {
+    let mut result = LinkedRegionResult::default();
+
+    // Linked regions that cannot be read are left untouched
+    if let Ok(region) = Region::from_file_name(linked_region_file) {
+        result.deleted_chunks = region
+            .get_chunks()
+            .iter()
+            .filter(|chunk| deleted_indices.contains(&chunk.index))
+            .count();
+        if result.deleted_chunks >= region.get_chunk_count() {
+            result.deleted_regions += 1;
+        }
+    }
+
+    result
+}
@@ -0,0 +1,116 @@
+use crate::commands::backup::{save_region_with_backup, Backup};
+use crate::commands::region_writer::is_out_of_space;
+use crate::commands::world_run::WorldRun;
+use crate::region_loader::chunk_loader::compression_scheme::CompressionScheme;
+use crate::region_loader::external_chunk::ExternalChunks;
+use crate::region_loader::recovery::scan_chunks;
+use crate::region_loader::region::Region;
+use crate::region_loader::region_format::RegionFormat;
+use crate::region_loader::region_position::RegionPosition;
+use crate::world::get_region_files::get_region_files;
+use flate2::Compression;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+pub fn execute_recover(
+    world_paths: &Vec<PathBuf>,
+    dimensions: &[String],
+    compression_scheme: CompressionScheme,
+    compression: Compression,
+    backup_path: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let entries = get_region_files(world_paths, dimensions)?;
+    let run = WorldRun::lock(world_paths)?;
+    let backup = backup_path
+        .map(|backup_path| Backup::create(backup_path, false))
+        .transpose()?;
+
+    let results = run.process(
+        &entries,
+        |entry| {
+            recover_region(
+                &entry.path,
+                compression_scheme,
+                compression,
+                backup.as_ref(),
+            )
+        },
+        |result| result.as_ref().is_err_and(is_out_of_space),
+    );
+
+    let mut recovered_chunks = 0;
+    for (entry, result) in results {
+        match result {
+            Ok(0) => {}
+            Ok(count) => {
+                println!("Recovered {count} chunks in {}", entry.path.display());
+                recovered_chunks += count;
+            }
+            Err(err) => println!("Cannot recover {}: {err}", entry.path.display()),
+        }
+    }
+    println!("Recovered Chunks: {recovered_chunks}");
+
+    if let Some(backup) = backup {
+        backup.finish()?;
+    }
+    if run.is_aborted() {
+        return Err("aborted, there is no space left on the disk".into());
+    }
+
+    Ok(())
+}
+
+/// Scans the sectors of regions with a damaged location table and rebuilds it with every chunk
+/// that can be found. Returns the number of recovered chunks.
+fn recover_region(
+    region_file_path: &Path,
+    compression_scheme: CompressionScheme,
+    compression: Compression,
+    backup: Option<&Backup>,
+) -> std::io::Result<usize> {
+    // Linear regions have no sectors to scan
+    if RegionFormat::from_file_name(region_file_path) == RegionFormat::Linear {
+        return Ok(0);
+    }
+    let Ok(mut region) = Region::from_file_name(region_file_path) else {
+        return Ok(0);
+    };
+
+    let bytes = std::fs::read(region_file_path)?;
+    let is_damaged = region.is_header_repaired()
+        || region
+            .get_raw_chunks()
+            .iter()
+            .any(|raw_chunk| raw_chunk.has_known_compression_scheme())
+        || (region.is_empty() && bytes.len() > 8192);
+    if !is_damaged {
+        return Ok(0);
+    }
+
+    // The timestamps of the location table may be lost, the file modification time is used instead
+    let timestamp = std::fs::metadata(region_file_path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as u32)
+        .unwrap_or(0);
+    let position = RegionPosition::from_file_name(region_file_path);
+    let external_chunks = ExternalChunks::from_region_file_name(region_file_path);
+    let recovered_chunks = scan_chunks(&bytes, position, timestamp, external_chunks.as_ref());
+    let recovered_chunks =
+        region.merge_recovered_chunks(recovered_chunks, external_chunks.as_ref());
+
+    if region.is_modified() {
+        save_region_with_backup(
+            backup,
+            region_file_path,
+            &region,
+            compression_scheme,
+            compression,
+            false,
+        )?;
+    }
+
+    Ok(recovered_chunks)
+}
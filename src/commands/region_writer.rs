@@ -0,0 +1,262 @@
+use crate::region_loader::chunk_loader::compression_scheme::CompressionScheme;
+use crate::region_loader::external_chunk::{get_staged_path, ExternalChunk};
+use crate::region_loader::region::Region;
+use crate::region_loader::region_format::RegionFormat;
+use flate2::Compression;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Writes the region in the format matching the extension of the file, along with its external
+/// chunk files
+pub fn save_region(
+    region_file_path: &Path,
+    region: &Region,
+    compression_scheme: CompressionScheme,
+    compression: Compression,
+    recompress: bool,
+) -> std::io::Result<()> {
+    save_region_with(
+        region_file_path,
+        region,
+        compression_scheme,
+        compression,
+        recompress,
+        |_| Ok(()),
+    )?;
+    Ok(())
+}
+
+/// Like [`save_region`], calling `before_write` with the external files about to be written.
+/// Returns the external files that were written.
+pub fn save_region_with(
+    region_file_path: &Path,
+    region: &Region,
+    compression_scheme: CompressionScheme,
+    compression: Compression,
+    recompress: bool,
+    before_write: impl FnOnce(&[PathBuf]) -> std::io::Result<()>,
+) -> std::io::Result<Vec<PathBuf>> {
+    if RegionFormat::from_file_name(region_file_path) == RegionFormat::Linear {
+        let bytes = region
+            .to_linear_bytes(compression)
+            .map_err(std::io::Error::other)?;
+        before_write(&[])?;
+        write_region_file(region_file_path, region, &bytes)?;
+        remove_orphaned_external_files(region.get_external_files(), &[])?;
+        return Ok(Vec::new());
+    }
+
+    let bytes = region
+        .to_bytes(compression_scheme, compression, recompress)
+        .map_err(std::io::Error::other)?;
+    let mut external_files = Vec::with_capacity(bytes.external_chunks.len());
+    if let Some(region_directory) = region_file_path.parent() {
+        for external_chunk in &bytes.external_chunks {
+            external_files.push(region_directory.join(external_chunk.file_name()));
+        }
+    }
+    before_write(&external_files)?;
+    // External files are staged first, so that they can be read when verifying the region, and
+    // only replace the original ones once the region file referencing them is written
+    let result = stage_external_files(&external_files, &bytes.external_chunks)
+        .and_then(|_| write_region_file(region_file_path, region, &bytes.region));
+    if let Err(err) = result {
+        for external_file in &external_files {
+            let _ = remove_file_if_exists(&get_staged_path(external_file));
+        }
+        return Err(err);
+    }
+    for external_file in &external_files {
+        std::fs::rename(get_staged_path(external_file), external_file)?;
+    }
+    sync_parent_directory(region_file_path);
+    remove_orphaned_external_files(region.get_external_files(), &external_files)?;
+    Ok(external_files)
+}
+
+fn stage_external_files(
+    external_files: &[PathBuf],
+    external_chunks: &[ExternalChunk],
+) -> std::io::Result<()> {
+    for (external_file, external_chunk) in external_files.iter().zip(external_chunks) {
+        write_atomically(
+            &get_staged_path(external_file),
+            &external_chunk.bytes,
+            |_| true,
+        )?;
+    }
+    Ok(())
+}
+
+pub fn remove_region(region_file_path: &Path, region: &Region) -> std::io::Result<()> {
+    std::fs::remove_file(region_file_path)?;
+    remove_orphaned_external_files(region.get_external_files(), &[])
+}
+
+/// Whether the error means the disk is full, in which case every later write would fail as well
+pub fn is_out_of_space(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::StorageFull | std::io::ErrorKind::QuotaExceeded
+    )
+}
+
+fn write_region_file(
+    region_file_path: &Path,
+    region: &Region,
+    bytes: &[u8],
+) -> std::io::Result<()> {
+    write_atomically(region_file_path, bytes, |written| {
+        region.verify_bytes(written, region_file_path)
+    })
+}
+
+/// Writes the file next to its destination, then renames it over the original file once it is
+/// synced to the disk and `verify` accepts what was read back from it. The original file is left
+/// untouched if anything fails.
+pub fn write_atomically(
+    path: &Path,
+    bytes: &[u8],
+    verify: impl Fn(&[u8]) -> bool,
+) -> std::io::Result<()> {
+    let temporary_path = get_temporary_path(path);
+    let result = write_and_verify(&temporary_path, bytes, verify)
+        .and_then(|_| std::fs::rename(&temporary_path, path));
+    if result.is_err() {
+        let _ = remove_file_if_exists(&temporary_path);
+        return result;
+    }
+
+    sync_parent_directory(path);
+    Ok(())
+}
+
+/// Persists the renames done in the directory of the file, not every platform allows syncing a
+/// directory
+fn sync_parent_directory(path: &Path) {
+    if let Some(directory) = path.parent().and_then(|parent| File::open(parent).ok()) {
+        let _ = directory.sync_all();
+    }
+}
+
+fn write_and_verify(
+    path: &Path,
+    bytes: &[u8],
+    verify: impl Fn(&[u8]) -> bool,
+) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+
+    if !verify(&std::fs::read(path)?) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("`{}` cannot be read back after writing it", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns a hidden file in the same directory, so that renaming it never crosses file systems
+fn get_temporary_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{file_name}.tmp"))
+}
+
+/// Deletes the `.mcc` files that were read with the region but were not written back, either
+/// because their chunk got deleted or because it is now small enough to fit in the region file
+fn remove_orphaned_external_files(
+    read_files: &[PathBuf],
+    written_files: &[PathBuf],
+) -> std::io::Result<()> {
+    for file in read_files {
+        if !written_files.contains(file) {
+            remove_file_if_exists(file)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn remove_file_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::tag::Tag;
+    use crate::region_loader::chunk_loader::chunk::Chunk;
+    use crate::region_loader::location::Location;
+    use crate::region_loader::region_position::RegionPosition;
+
+    #[test]
+    fn test_external_files_replaced_after_region() {
+        // Random data does not compress, making the chunk larger than 255 sectors
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let noise = (0..160_000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as i64
+            })
+            .collect();
+        let nbt = Tag::Compound {
+            name: None,
+            value: Vec::from([
+                Tag::Int {
+                    name: Some(String::from("xPos")),
+                    value: 0,
+                },
+                Tag::Int {
+                    name: Some(String::from("zPos")),
+                    value: 0,
+                },
+                Tag::LongArray {
+                    name: Some(String::from("noise")),
+                    value: noise,
+                },
+            ]),
+        };
+        let location = Location::new(8192, 4096, 0).unwrap();
+        let mut region = Region::new(RegionPosition { x: 0, z: 0 });
+        let chunk = Chunk::from_nbt_bytes(&nbt.to_bytes(), location, 0).unwrap();
+        region.insert_chunk(chunk).unwrap();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let region_file_path = temp_dir.path().join("r.0.0.mca");
+        let external_file = temp_dir.path().join("c.0.0.mcc");
+        let save = || {
+            let compression = Compression::fast();
+            save_region(
+                &region_file_path,
+                &region,
+                CompressionScheme::Zlib,
+                compression,
+                true,
+            )
+        };
+
+        // The original external file is kept when the region file cannot be written
+        std::fs::write(&external_file, b"original").unwrap();
+        std::fs::create_dir(&region_file_path).unwrap();
+        assert!(save().is_err());
+        assert_eq!(std::fs::read(&external_file).unwrap(), b"original");
+        assert!(!get_staged_path(&external_file).exists());
+
+        std::fs::remove_dir(&region_file_path).unwrap();
+        save().unwrap();
+        assert!(!get_staged_path(&external_file).exists());
+        let saved_region = Region::from_file_name(&region_file_path).unwrap();
+        assert_eq!(saved_region.get_external_files(), &vec![external_file]);
+        assert_eq!(saved_region.get_chunks()[0].nbt(), &nbt);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::commands::backup::{
+    get_sha256, is_tar_archive, parse_manifest, read_tar_manifest, ManifestEntry,
+    MANIFEST_FILE_NAME,
+};
+use crate::commands::region_writer::{remove_file_if_exists, write_atomically};
+use crate::commands::world_run::WorldRun;
+use crate::region_loader::region_position::RegionPosition;
+use crate::world::area::Area;
+use crate::world::dimension::get_dimensions;
+use crate::world::validate::validate_worlds;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Limits the files restored from a backup
+#[derive(Default)]
+pub struct RestoreFilter<'a> {
+    /// Only restore these dimensions, or every dimension if empty
+    pub dimensions: &'a [String],
+    pub area: Option<Area>,
+}
+
+enum RestoreAction {
+    Restore,
+    /// The file did not exist before the run
+    Remove,
+    AlreadyRestored,
+    /// The file was modified since the run, most likely by the game
+    Modified,
+}
+
+/// Puts back the files a write run copied in its backup. Files are only overwritten when they
+/// are still the ones written by the run, unless `force` is set.
+pub fn execute_restore(
+    world_paths: &Vec<PathBuf>,
+    backup_path: &Path,
+    filter: RestoreFilter,
+    force: bool,
+) -> Result<(), Box<dyn Error>> {
+    let worlds = validate_worlds(world_paths)?
+        .iter()
+        .map(std::path::absolute)
+        .collect::<Result<Vec<_>, _>>()?;
+    let _run = WorldRun::lock(world_paths)?;
+    let entries = read_manifest(backup_path)?
+        .into_iter()
+        .filter(|entry| is_selected(entry, &worlds, &filter))
+        .collect::<Vec<_>>();
+
+    let mut files_to_restore = HashMap::new();
+    let (mut removed_files, mut already_restored_files, mut modified_files) = (0, 0, 0);
+    for entry in &entries {
+        match get_restore_action(entry, force)? {
+            RestoreAction::Restore => {
+                if let Some(path) = &entry.path {
+                    files_to_restore.insert(path.as_str(), entry);
+                }
+            }
+            RestoreAction::Remove => {
+                remove_file_if_exists(&entry.original_path)?;
+                removed_files += 1;
+            }
+            RestoreAction::AlreadyRestored => already_restored_files += 1,
+            RestoreAction::Modified => {
+                println!(
+                    "Skipping {}, it was modified since the run, use `--force` to restore it anyway",
+                    entry.original_path.display()
+                );
+                modified_files += 1;
+            }
+        }
+    }
+
+    let restored_files = files_to_restore.len();
+    if is_tar_archive(backup_path) {
+        let mut archive = tar::Archive::new(File::open(backup_path)?);
+        for file in archive.entries()? {
+            let mut file = file?;
+            let path = file.path()?.to_string_lossy().to_string();
+            if let Some(entry) = files_to_restore.remove(path.as_str()) {
+                let mut bytes = Vec::new();
+                file.read_to_end(&mut bytes)?;
+                restore_file(entry, &bytes)?;
+            }
+        }
+    } else {
+        for (path, entry) in files_to_restore.drain() {
+            restore_file(entry, &std::fs::read(backup_path.join(path))?)?;
+        }
+    }
+    if let Some(entry) = files_to_restore.values().next() {
+        return Err(RestoreError::MissingCopy(entry.original_path.clone()).into());
+    }
+
+    println!("Restored Files: {restored_files}");
+    println!("Removed Files: {removed_files}");
+    println!("Already Restored Files: {already_restored_files}");
+    println!("Modified Files: {modified_files}");
+
+    Ok(())
+}
+
+fn read_manifest(backup_path: &Path) -> Result<Vec<ManifestEntry>, Box<dyn Error>> {
+    if !is_tar_archive(backup_path) {
+        let content = std::fs::read_to_string(backup_path.join(MANIFEST_FILE_NAME))
+            .map_err(|_| RestoreError::MissingManifest(backup_path.to_path_buf()))?;
+        return Ok(parse_manifest(&content));
+    }
+
+    let (manifest, _) = read_tar_manifest(&File::open(backup_path)?)?;
+    manifest
+        .map(|content| parse_manifest(&content))
+        .ok_or_else(|| RestoreError::MissingManifest(backup_path.to_path_buf()).into())
+}
+
+/// Whether the file belongs to one of the worlds, and to the dimension and area to restore
+fn is_selected(entry: &ManifestEntry, worlds: &[PathBuf], filter: &RestoreFilter) -> bool {
+    let Some(world) = worlds
+        .iter()
+        .find(|world| entry.original_path.starts_with(world))
+    else {
+        return false;
+    };
+
+    if !filter.dimensions.is_empty() {
+        // Region files are stored in the `region`, `entities` or `poi` directory of the dimension
+        let dimension_directory = entry.original_path.parent().and_then(Path::parent);
+        let is_in_dimension = get_dimensions(world).iter().any(|candidate| {
+            filter.dimensions.contains(&candidate.id)
+                && Some(candidate.directory.as_path()) == dimension_directory
+        });
+        if !is_in_dimension {
+            return false;
+        }
+    }
+
+    match (filter.area, get_region_position(&entry.original_path)) {
+        (Some(area), Some(position)) => area.intersects_region(position),
+        (Some(_), None) => false,
+        (None, _) => true,
+    }
+}
+
+/// Parses the region of region files, as well as external chunk files named `c.<x>.<z>.mcc`
+fn get_region_position(path: &Path) -> Option<RegionPosition> {
+    if let Some(position) = RegionPosition::from_file_name(path) {
+        return Some(position);
+    }
+    let file_name = path.file_name()?.to_str()?;
+    let mut parts = file_name.split('.');
+    if parts.next()? != "c" {
+        return None;
+    }
+    let x = parts.next()?.parse::<i32>().ok()?;
+    let z = parts.next()?.parse::<i32>().ok()?;
+    Some(RegionPosition {
+        x: x.div_euclid(32),
+        z: z.div_euclid(32),
+    })
+}
+
+fn get_restore_action(entry: &ManifestEntry, force: bool) -> std::io::Result<RestoreAction> {
+    let current_sha256 = match std::fs::read(&entry.original_path) {
+        Ok(bytes) => Some(get_sha256(&bytes)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+
+    let action = if current_sha256 == entry.original_sha256 {
+        RestoreAction::AlreadyRestored
+    } else if current_sha256 != entry.written_sha256 && !force {
+        RestoreAction::Modified
+    } else if entry.original_sha256.is_none() {
+        RestoreAction::Remove
+    } else {
+        RestoreAction::Restore
+    };
+    Ok(action)
+}
+
+fn restore_file(entry: &ManifestEntry, bytes: &[u8]) -> Result<(), RestoreError> {
+    let original_sha256 = entry.original_sha256.as_deref();
+    if original_sha256 != Some(&get_sha256(bytes)) {
+        return Err(RestoreError::CorruptedCopy(entry.original_path.clone()));
+    }
+    if let Some(parent) = entry.original_path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| RestoreError::Write(entry.original_path.clone(), err))?;
+    }
+    write_atomically(&entry.original_path, bytes, |written| {
+        original_sha256 == Some(&get_sha256(written))
+    })
+    .map_err(|err| RestoreError::Write(entry.original_path.clone(), err))
+}
+
+#[derive(Error, Debug)]
+pub enum RestoreError {
+    #[error("the backup `{0}` has no manifest")]
+    MissingManifest(PathBuf),
+    #[error("the copy of `{0}` is missing from the backup")]
+    MissingCopy(PathBuf),
+    #[error("the copy of `{0}` does not match the hash of the manifest")]
+    CorruptedCopy(PathBuf),
+    #[error("cannot restore `{0}`: {1}")]
+    Write(PathBuf, std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::backup::Backup;
+
+    #[test]
+    fn test_restore() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let directory = temp_dir.path().to_path_buf();
+        let world = directory.join("world");
+        let trimmed_file = world.join("region/r.0.0.mca");
+        let played_file = world.join("region/r.1.0.mca");
+        std::fs::create_dir_all(trimmed_file.parent().unwrap()).unwrap();
+        std::fs::write(world.join("level.dat"), b"").unwrap();
+        std::fs::write(&trimmed_file, b"region").unwrap();
+        std::fs::write(&played_file, b"region").unwrap();
+
+        let backup_path = directory.join("backup.tar");
+        let backup = Backup::create(&backup_path, false).unwrap();
+        for file in [&trimmed_file, &played_file] {
+            backup.save_file(file).unwrap();
+            std::fs::write(file, b"trimmed").unwrap();
+            backup.record_file(file).unwrap();
+        }
+        backup.finish().unwrap();
+        // The game saved the second region after the run
+        std::fs::write(&played_file, b"played").unwrap();
+
+        let world_paths = vec![world.clone()];
+        execute_restore(&world_paths, &backup_path, RestoreFilter::default(), false).unwrap();
+        assert_eq!(std::fs::read(&trimmed_file).unwrap(), b"region");
+        assert_eq!(std::fs::read(&played_file).unwrap(), b"played");
+
+        // Only the regions inside the area are restored
+        let filter = RestoreFilter {
+            dimensions: &[],
+            area: Some("0,0,100,100".parse().unwrap()),
+        };
+        execute_restore(&world_paths, &backup_path, filter, true).unwrap();
+        assert_eq!(std::fs::read(&played_file).unwrap(), b"played");
+        execute_restore(&world_paths, &backup_path, RestoreFilter::default(), true).unwrap();
+        assert_eq!(std::fs::read(&played_file).unwrap(), b"region");
+    }
+}
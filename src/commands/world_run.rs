@@ -0,0 +1,66 @@
+use crate::world::session_lock::{lock_worlds, SessionLock, SessionLockError};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::iter::ParallelIterator;
+use rayon::prelude::IntoParallelRefIterator;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The setup shared by the modes modifying the world. The worlds stay locked until the run is
+/// dropped, so that the game cannot open them meanwhile.
+pub struct WorldRun {
+    _session_locks: Vec<SessionLock>,
+    is_aborted: AtomicBool,
+}
+
+impl WorldRun {
+    pub fn lock(world_paths: &[PathBuf]) -> Result<Self, SessionLockError> {
+        Ok(Self {
+            _session_locks: lock_worlds(world_paths)?,
+            is_aborted: AtomicBool::new(false),
+        })
+    }
+
+    /// For worlds of a running server, which already holds their lock
+    pub fn unlocked() -> Self {
+        Self {
+            _session_locks: Vec::new(),
+            is_aborted: AtomicBool::new(false),
+        }
+    }
+
+    /// Processes the entries in parallel while showing the progress. Once `is_out_of_space`
+    /// returns true for a result, the remaining entries are skipped instead of failing one by one.
+    pub fn process<'a, T: Sync, R: Send>(
+        &self,
+        entries: &'a [T],
+        process: impl Fn(&'a T) -> R + Sync,
+        is_out_of_space: impl Fn(&R) -> bool + Sync,
+    ) -> Vec<(&'a T, R)> {
+        let pb = ProgressBar::new(entries.len() as u64);
+        let style = ProgressStyle::with_template(
+            "{percent}% {bar} {pos}/{len} [{elapsed_precise}>{eta_precise}, {per_sec}]",
+        )
+        .unwrap();
+        pb.set_style(style);
+
+        entries
+            .par_iter()
+            .filter_map(|entry| {
+                if self.is_aborted.load(Ordering::Relaxed) {
+                    return None;
+                }
+                let result = process(entry);
+                if is_out_of_space(&result) {
+                    self.is_aborted.store(true, Ordering::Relaxed);
+                }
+                pb.inc(1);
+                Some((entry, result))
+            })
+            .collect()
+    }
+
+    /// Whether regions were skipped because the disk is full
+    pub fn is_aborted(&self) -> bool {
+        self.is_aborted.load(Ordering::Relaxed)
+    }
+}
@@ -1,76 +1,349 @@
-use crate::commands::optimize_result::{reduce_optimize_results, OptimizeResult};
-use crate::region_loader::region::Region;
+use crate::commands::backup::{
+    remove_file_with_backup, remove_region_with_backup, save_region_with_backup, Backup,
+};
+use crate::commands::corrupted_chunks::quarantine_corrupted_chunks;
+use crate::commands::journal::Journal;
+use crate::commands::live_server::{LiveServer, RconOptions};
+use crate::commands::misplaced_chunks::{find_misplaced_chunks, move_misplaced_chunks};
+use crate::commands::optimize_result::{
+    count_unsupported_chunks, print_results, LinkedRegionResult, OptimizeResult, WorldDimension,
+};
+use crate::commands::region_writer::is_out_of_space;
+use crate::commands::world_run::WorldRun;
+use crate::region_loader::chunk_loader::compression_scheme::CompressionScheme;
+use crate::region_loader::region::{ParseRegionError, Region};
+use crate::trim_policy::TrimPolicy;
 use crate::world::get_region_files::get_region_files;
+use crate::world::linked_regions::LinkedRegion;
 use flate2::Compression;
-use indicatif::{ProgressBar, ProgressStyle};
-use rayon::iter::ParallelIterator;
-use rayon::prelude::IntoParallelRefIterator;
+use std::collections::HashSet;
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub struct WriteOptions<'a> {
+    /// Only trim these dimensions, or every dimension if empty
+    pub dimensions: &'a [String],
+    pub compression_scheme: CompressionScheme,
+    pub compression: Compression,
+    pub recompress: bool,
+    pub quarantine_directory: Option<&'a Path>,
+    pub fix_misplaced: bool,
+    pub backup_path: Option<&'a Path>,
+    pub policy: TrimPolicy,
+    /// Trim the world while a server is running, instead of requiring it to be closed
+    pub rcon: Option<RconOptions<'a>>,
+}
 
 pub fn execute_write(
     world_paths: &Vec<PathBuf>,
-    compression: Compression,
+    options: &WriteOptions,
 ) -> Result<(), Box<dyn Error>> {
-    let entries = get_region_files(world_paths)?;
-    let pb = ProgressBar::new(entries.len() as u64);
-    let style = ProgressStyle::with_template(
-        "{percent}% {bar} {pos}/{len} [{elapsed_precise}>{eta_precise}, {per_sec}]",
-    )
-    .unwrap();
-    pb.set_style(style);
-
-    let mut results = entries
-        .par_iter()
-        .flat_map(|entry| {
-            let result = optimize_write(entry, compression);
-            pb.inc(1);
-            result
+    let entries = get_region_files(world_paths, options.dimensions)?;
+    // A running server already holds the lock, its saves are turned off instead, and turned back
+    // on when `live_server` is dropped, even on error
+    let (run, live_server) = match &options.rcon {
+        None => (WorldRun::lock(world_paths)?, None),
+        Some(rcon) => (
+            WorldRun::unlocked(),
+            Some(Mutex::new(LiveServer::start(rcon, world_paths)?)),
+        ),
+    };
+    // Players move during the run, so the regions are checked right before being processed
+    let is_loaded = |dimension: &str, region_file_path: &Path| match &live_server {
+        Some(live_server) => live_server
+            .lock()
+            .unwrap()
+            .is_loaded(dimension, region_file_path)
+            .map_err(std::io::Error::other),
+        None => Ok(false),
+    };
+    let worlds = world_paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+    // Resuming with other settings would trim the remaining regions differently
+    let settings = format!(
+        "min-inhabited-time {}, fix-misplaced {}",
+        options.policy, options.fix_misplaced
+    );
+    let mut journal = Journal::open(&worlds, &settings)?;
+    let is_resuming = journal.get_entry_count() > 0;
+    if is_resuming {
+        println!(
+            "Resuming the previous run, {} regions are already done",
+            journal.get_entry_count()
+        );
+    }
+    let backup = options
+        .backup_path
+        .map(|backup_path| Backup::create(backup_path, is_resuming))
+        .transpose()?;
+    let entries = entries
+        .iter()
+        .filter(|entry| !journal.contains(&entry.path))
+        .collect::<Vec<_>>();
+    // Regions loaded by the server are skipped and have no result
+    let results = run.process(
+        &entries,
+        |entry| match is_loaded(&entry.dimension, &entry.path) {
+            Ok(false) => Some(
+                optimize_write(&entry.path, &entry.dimension, options, backup.as_ref()).and_then(
+                    |result| {
+                        journal.record(&entry.world, &entry.path, &entry.dimension, &result)?;
+                        Ok(result)
+                    },
+                ),
+            ),
+            Ok(true) => None,
+            Err(err) => Some(Err(err)),
+        },
+        |result| matches!(result, Some(Err(err)) if is_out_of_space(err)),
+    );
+
+    // The results of the previous run are combined with this one
+    let mut optimize_results = journal
+        .take_entries()
+        .into_iter()
+        .map(|entry| {
+            let dimension = WorldDimension {
+                world: entry.world,
+                dimension: entry.dimension,
+            };
+            (dimension, entry.result)
         })
-        .collect::<Vec<OptimizeResult>>();
+        .collect::<Vec<_>>();
+    let mut loaded_entries = 0;
+    for (entry, result) in results {
+        match result {
+            Some(Ok(result)) => optimize_results.push((WorldDimension::from(*entry), result)),
+            Some(Err(err)) => println!("Cannot write {}: {err}", entry.path.display()),
+            None => loaded_entries += 1,
+        }
+    }
+
+    // Chunks are not moved into regions the server may overwrite
+    let misplaced_chunks = optimize_results
+        .iter()
+        .flat_map(|(dimension, result)| {
+            result.misplaced_chunks.iter().filter(|misplaced_chunk| {
+                misplaced_chunk
+                    .target_region_file_path
+                    .as_deref()
+                    .is_none_or(|target| {
+                        is_loaded(&dimension.dimension, target).is_ok_and(|is_loaded| !is_loaded)
+                    })
+            })
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    print_results(optimize_results);
+    if loaded_entries > 0 {
+        println!(
+            "Skipped {loaded_entries} regions loaded by the server, run again later to trim them"
+        );
+    }
+
+    if run.is_aborted() {
+        // The backup is left as it is, to be resumed by the next run
+        return Err(
+            "aborted, there is no space left on the disk, run the write mode again to resume"
+                .into(),
+        );
+    }
 
-    let result = reduce_optimize_results(&mut results);
-    println!("{result}");
+    if options.fix_misplaced && !misplaced_chunks.is_empty() {
+        // Moving chunks is done sequentially as several chunks may be moved to the same region
+        let (moved_chunks, conflicts) = move_misplaced_chunks(
+            &misplaced_chunks,
+            options.compression_scheme,
+            options.compression,
+            options.recompress,
+            backup.as_ref(),
+        )?;
+        for conflict in conflicts {
+            println!("Cannot move, the slot is already used: {conflict}");
+        }
+        println!("Moved Misplaced Chunks: {moved_chunks}");
+    }
 
+    if let Some(backup) = backup {
+        backup.finish()?;
+    }
+    journal.remove()?;
     Ok(())
 }
 
 fn optimize_write(
-    region_file_path: &PathBuf,
-    compression: Compression,
+    region_file_path: &Path,
+    dimension: &str,
+    options: &WriteOptions,
+    backup: Option<&Backup>,
 ) -> std::io::Result<OptimizeResult> {
     let mut result = OptimizeResult::default();
 
-    match Region::from_file_name(region_file_path) {
-        Ok(mut region) => {
+    let mut region = Region::from_file_name(region_file_path);
+    let deleted_indices = match &mut region {
+        Ok(region) => {
             result.total_chunks += region.get_chunk_count();
+            result.unsupported_chunks += count_unsupported_chunks(region);
+            if region.is_header_repaired() {
+                result.repaired_regions += 1;
+            }
+            result.corrupted_chunks = quarantine_corrupted_chunks(
+                region_file_path,
+                region,
+                options.quarantine_directory,
+            )?;
 
-            let chunks_to_delete_indices: Vec<_> = region
-                .get_chunks()
-                .iter()
-                .enumerate()
-                .filter_map(|(i, chunk)| if chunk.should_delete() { Some(i) } else { None })
-                .collect();
-            result.deleted_chunks += chunks_to_delete_indices.len();
+            let deleted_indices = options.policy.for_dimension(dimension).apply(region);
+            result.deleted_chunks += deleted_indices.len();
 
-            for &index in chunks_to_delete_indices.iter().rev() {
-                region.remove_chunk_by_index(index);
+            result.misplaced_chunks = find_misplaced_chunks(region_file_path, region);
+            let has_wrong_slots = result
+                .misplaced_chunks
+                .iter()
+                .any(|misplaced_chunk| misplaced_chunk.target_region_file_path.is_none());
+            if options.fix_misplaced && has_wrong_slots {
+                // Writing the region places chunks in the slot matching their position
+                region.mark_modified();
             }
+            deleted_indices
+        }
+        // Too small to hold any chunk
+        Err(ParseRegionError::HeaderError) => (0..1024).collect(),
+        Err(err) => {
+            // Never delete a region that may still hold chunks
+            result
+                .unreadable_regions
+                .push(format!("Cannot read {}: {err}", region_file_path.display()));
+            HashSet::new()
+        }
+    };
 
-            if region.is_empty() {
-                result.deleted_regions += 1;
-                std::fs::remove_file(region_file_path)?;
-            } else if region.is_modified() {
-                // Only write the region file if it has been modified
-                let bytes = region.to_bytes(compression);
-                std::fs::write(region_file_path, bytes)?;
-            }
+    // Linked regions are trimmed before the terrain, as the deleted chunks can no longer be found
+    // once the terrain region is written if the run is interrupted in between
+    for linked_region in LinkedRegion::ALL {
+        if let Some(linked_region_file) = linked_region.get_region_file(region_file_path) {
+            *result.get_linked_region_result(linked_region) =
+                trim_linked_region(&linked_region_file, &deleted_indices, options, backup)?;
         }
-        Err(_) => {
+    }
+
+    match region {
+        Ok(region) if region.is_empty() => {
+            result.deleted_regions += 1;
+            remove_region_with_backup(backup, region_file_path, &region)?;
+        }
+        Ok(region) if region.is_modified() => {
+            // Only write the region file if it has been modified
+            save_region_with_backup(
+                backup,
+                region_file_path,
+                &region,
+                options.compression_scheme,
+                options.compression,
+                options.recompress,
+            )?;
+        }
+        Err(ParseRegionError::HeaderError) => {
             result.deleted_regions += 1;
-            std::fs::remove_file(region_file_path)?;
+            remove_file_with_backup(backup, region_file_path)?;
         }
+        _ => {}
+    }
+
+    Ok(result)
+}
+
+/// Deletes the chunks of a linked region matching the deleted terrain chunks. Linked chunks such as
+/// entities or POIs have no status of their own, so they are only matched by their slot.
+fn trim_linked_region(
+    linked_region_file: &Path,
+    deleted_indices: &HashSet<usize>,
+    options: &WriteOptions,
+    backup: Option<&Backup>,
+) -> std::io::Result<LinkedRegionResult> {
+    let mut result = LinkedRegionResult::default();
+
+    // Linked regions that cannot be read are left untouched
+    let Ok(mut region) = Region::from_file_name(linked_region_file) else {
+        return Ok(result);
+    };
+
+    let chunks_to_delete_indices: Vec<_> = region
+        .get_chunks()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, chunk)| deleted_indices.contains(&chunk.index).then_some(i))
+        .collect();
+    result.deleted_chunks += chunks_to_delete_indices.len();
+
+    for &index in chunks_to_delete_indices.iter().rev() {
+        region.remove_chunk_by_index(index);
+    }
+
+    if region.is_empty() {
+        result.deleted_regions += 1;
+        remove_region_with_backup(backup, linked_region_file, &region)?;
+    } else if region.is_modified() {
+        save_region_with_backup(
+            backup,
+            linked_region_file,
+            &region,
+            options.compression_scheme,
+            options.compression,
+            options.recompress,
+        )?;
     }
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_linked_region() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let entities_file = temp_dir.path().join("entities/r.-1.-1.mca");
+        std::fs::create_dir_all(entities_file.parent().unwrap()).unwrap();
+        std::fs::copy("test_files/r.-1.-1.mca", &entities_file).unwrap();
+        let options = WriteOptions {
+            dimensions: &[],
+            compression_scheme: CompressionScheme::Zlib,
+            compression: Compression::default(),
+            recompress: false,
+            quarantine_directory: None,
+            fix_misplaced: false,
+            backup_path: None,
+            policy: TrimPolicy::default(),
+            rcon: None,
+        };
+
+        let region = Region::from_file_name(&entities_file).unwrap();
+        let indices: Vec<_> = region
+            .get_chunks()
+            .iter()
+            .map(|chunk| chunk.index)
+            .collect();
+        let deleted_indices = HashSet::from([indices[0], indices[1]]);
+
+        // Only the chunks in the deleted slots are removed
+        let result = trim_linked_region(&entities_file, &deleted_indices, &options, None).unwrap();
+        assert_eq!((result.deleted_chunks, result.deleted_regions), (2, 0));
+        let region = Region::from_file_name(&entities_file).unwrap();
+        assert_eq!(region.get_chunk_count(), indices.len() - 2);
+        assert!(region
+            .get_chunks()
+            .iter()
+            .all(|chunk| !deleted_indices.contains(&chunk.index)));
+
+        // A region left empty is deleted
+        let deleted_indices = (0..1024).collect();
+        let result = trim_linked_region(&entities_file, &deleted_indices, &options, None).unwrap();
+        assert_eq!(
+            (result.deleted_chunks, result.deleted_regions),
+            (indices.len() - 2, 1)
+        );
+        assert!(!entities_file.exists());
+    }
+}
@@ -0,0 +1,328 @@
+use crate::cli::{
+    Cli, CompressionAlgorithm, COMPRESSION_LEVEL_ERROR, LOADED_RADIUS_ERROR, MAX_COMPRESSION_LEVEL,
+};
+use minecraft_world_trimmer::commands::MAX_LOADED_RADIUS;
+use minecraft_world_trimmer::TrimPolicy;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+const DEFAULT_LOADED_RADIUS: u32 = 32;
+
+/// The settings of a run, read from the file given with `--config` then overridden by the
+/// command-line flags
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub worlds: Vec<PathBuf>,
+    pub server: bool,
+    /// Only these dimensions are processed, or every dimension if empty
+    pub dimensions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantine_dir: Option<PathBuf>,
+    pub fix_misplaced: bool,
+    /// Where the files modified by the run are copied, or the backup to restore
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+    pub compression: CompressionConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rcon: Option<RconConfig>,
+    /// The deletion rules
+    pub trim: TrimPolicy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CompressionConfig {
+    pub level: u32,
+    pub scheme: CompressionAlgorithm,
+    pub recompress: bool,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_COMPRESSION_LEVEL,
+            scheme: CompressionAlgorithm::default(),
+            recompress: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RconConfig {
+    pub address: String,
+    pub password: String,
+    #[serde(default = "default_loaded_radius")]
+    pub loaded_radius: u32,
+}
+
+fn default_loaded_radius() -> u32 {
+    DEFAULT_LOADED_RADIUS
+}
+
+impl Config {
+    /// Reads the configuration file if any, then applies the command-line flags on top of it
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|err| ConfigError::Read(path.clone(), err))?;
+                let mut config: Config = toml::from_str(&content)
+                    .map_err(|err| ConfigError::Parse(path.clone(), err))?;
+                config.resolve_paths(path.parent().unwrap_or(Path::new("")));
+                config
+            }
+            None => Config::default(),
+        };
+        config.merge(cli)?;
+
+        if config.compression.level > MAX_COMPRESSION_LEVEL {
+            return Err(ConfigError::Invalid(COMPRESSION_LEVEL_ERROR));
+        }
+        if let Some(rcon) = &config.rcon {
+            if rcon.loaded_radius > MAX_LOADED_RADIUS {
+                return Err(ConfigError::Invalid(LOADED_RADIUS_ERROR));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Makes the relative paths of the configuration file relative to its directory rather than
+    /// to the working directory
+    fn resolve_paths(&mut self, directory: &Path) {
+        for world in &mut self.worlds {
+            *world = directory.join(&*world);
+        }
+        if let Some(quarantine_dir) = &mut self.quarantine_dir {
+            *quarantine_dir = directory.join(&*quarantine_dir);
+        }
+        if let Some(backup) = &mut self.backup {
+            *backup = directory.join(&*backup);
+        }
+    }
+
+    fn merge(&mut self, cli: &Cli) -> Result<(), ConfigError> {
+        if !cli.world_paths.is_empty() {
+            self.worlds = cli.world_paths.clone();
+        }
+        merge_flag(&mut self.server, cli.server, cli.no_server);
+        if !cli.dimension.is_empty() {
+            self.dimensions = cli.dimension.clone();
+        }
+        if cli.quarantine_dir.is_some() {
+            self.quarantine_dir = cli.quarantine_dir.clone();
+        }
+        merge_flag(
+            &mut self.fix_misplaced,
+            cli.fix_misplaced,
+            cli.no_fix_misplaced,
+        );
+        if cli.backup.is_some() {
+            self.backup = cli.backup.clone();
+        }
+        for min_inhabited_time in &cli.min_inhabited_time {
+            match &min_inhabited_time.dimension {
+                Some(dimension) => {
+                    self.trim
+                        .dimension_min_inhabited_time
+                        .insert(dimension.clone(), min_inhabited_time.time);
+                }
+                None => self.trim.min_inhabited_time = min_inhabited_time.time,
+            }
+        }
+
+        if let Some(level) = cli.compression_level {
+            self.compression.level = level;
+        }
+        if let Some(scheme) = cli.compression_scheme {
+            self.compression.scheme = scheme;
+        }
+        merge_flag(
+            &mut self.compression.recompress,
+            cli.recompress,
+            cli.no_recompress,
+        );
+
+        if let Some(address) = &cli.rcon {
+            let password = cli
+                .rcon_password
+                .clone()
+                .or_else(|| self.rcon.as_ref().map(|rcon| rcon.password.clone()))
+                .ok_or(ConfigError::Invalid("`--rcon` requires `--rcon-password`"))?;
+            let loaded_radius = self
+                .rcon
+                .as_ref()
+                .map_or(DEFAULT_LOADED_RADIUS, |rcon| rcon.loaded_radius);
+            self.rcon = Some(RconConfig {
+                address: address.clone(),
+                password,
+                loaded_radius,
+            });
+        }
+        if let Some(rcon) = &mut self.rcon {
+            if let Some(password) = &cli.rcon_password {
+                rcon.password = password.clone();
+            }
+            if let Some(loaded_radius) = cli.loaded_radius {
+                rcon.loaded_radius = loaded_radius;
+            }
+        }
+        Ok(())
+    }
+
+    /// Formats the configuration as TOML, without the RCON password
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        let mut config = self.clone();
+        if let Some(rcon) = &mut config.rcon {
+            rcon.password = String::from("<hidden>");
+        }
+        toml::to_string_pretty(&config)
+    }
+}
+
+/// Applies a `--<flag>` or `--no-<flag>` pair, keeping the value of the file when neither is set
+fn merge_flag(value: &mut bool, flag: bool, no_flag: bool) {
+    if flag {
+        *value = true;
+    } else if no_flag {
+        *value = false;
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("cannot read the configuration file `{0}`: {1}")]
+    Read(PathBuf, std::io::Error),
+    #[error("invalid configuration file `{0}`: {1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("{0}")]
+    Invalid(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use minecraft_world_trimmer::Ticks;
+
+    #[test]
+    fn test_merge() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("trimmer.toml");
+        std::fs::write(
+            &file,
+            r#"
+            worlds = ["world", "world_nether"]
+            dimensions = ["minecraft:overworld"]
+            quarantine-dir = "quarantine"
+            fix-misplaced = true
+            backup = "backup.tar"
+
+            [compression]
+            level = 9
+            scheme = "lz4"
+            recompress = true
+
+            [rcon]
+            address = "localhost:25575"
+            password = "secret"
+            "#,
+        )
+        .unwrap();
+
+        let cli = Cli::try_parse_from([
+            "minecraft_world_trimmer",
+            "write",
+            "--config",
+            file.to_str().unwrap(),
+            "--compression-level",
+            "3",
+            "--no-recompress",
+            "--loaded-radius",
+            "8",
+            "--min-inhabited-time",
+            "5m",
+            "--min-inhabited-time",
+            "minecraft:the_nether=30s",
+        ])
+        .unwrap();
+        let config = Config::load(&cli).unwrap();
+        // The paths of the file are relative to its directory
+        assert_eq!(
+            config.worlds,
+            [
+                temp_dir.path().join("world"),
+                temp_dir.path().join("world_nether")
+            ]
+        );
+        assert_eq!(
+            config.quarantine_dir,
+            Some(temp_dir.path().join("quarantine"))
+        );
+        assert!(config.fix_misplaced);
+        assert_eq!(config.backup, Some(temp_dir.path().join("backup.tar")));
+        assert!(!config.compression.recompress);
+        assert_eq!(config.dimensions, ["minecraft:overworld"]);
+        assert_eq!(config.compression.level, 3);
+        assert_eq!(config.compression.scheme, CompressionAlgorithm::Lz4);
+        assert_eq!(
+            config.trim.get_min_inhabited_time("minecraft:overworld"),
+            Ticks(6000)
+        );
+        assert_eq!(
+            config.trim.get_min_inhabited_time("minecraft:the_nether"),
+            Ticks(600)
+        );
+        let rcon = config.rcon.as_ref().unwrap();
+        assert_eq!((rcon.password.as_str(), rcon.loaded_radius), ("secret", 8));
+
+        let printed = config.to_toml().unwrap();
+        assert!(printed.contains("level = 3"));
+        assert!(printed.contains("min-inhabited-time = \"5m\""));
+        assert!(!printed.contains("secret"));
+
+        // The command-line world paths replace the ones of the file
+        let cli = Cli::try_parse_from([
+            "minecraft_world_trimmer",
+            "check",
+            "other_world",
+            "--config",
+            file.to_str().unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(
+            Config::load(&cli).unwrap().worlds,
+            [PathBuf::from("other_world")]
+        );
+
+        // The worlds are not needed to print the configuration
+        let print_cli =
+            Cli::try_parse_from(["minecraft_world_trimmer", "print-config", "--backup", "b"])
+                .unwrap();
+        assert_eq!(
+            Config::load(&print_cli).unwrap().backup,
+            Some(PathBuf::from("b"))
+        );
+
+        assert!(Cli::try_parse_from([
+            "minecraft_world_trimmer",
+            "write",
+            "world",
+            "--loaded-radius=-1",
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "minecraft_world_trimmer",
+            "write",
+            "world",
+            "--loaded-radius=100000",
+        ])
+        .is_err());
+
+        std::fs::write(&file, "unknown = 1").unwrap();
+        assert!(matches!(Config::load(&cli), Err(ConfigError::Parse(..))));
+    }
+}
@@ -0,0 +1,46 @@
+//! Reads, trims and writes the region files of Minecraft worlds.
+//!
+//! The `minecraft_world_trimmer` binary is a front end to this crate, which can also be used to
+//! build other tools:
+//! - [`Region`] opens Anvil and Linear region files, and [`Region::iter_chunks`] iterates over
+//!   their chunks along with their coordinates.
+//! - [`nbt::tag::Tag`] holds the NBT data of a chunk, read with [`nbt::tag::Tag::from_bytes`] and
+//!   written with [`nbt::tag::Tag::to_bytes`].
+//! - [`TrimPolicy`] decides which chunks of a region are deleted.
+//! - [`commands`] holds the modes of the binary, running on whole worlds.
+//!
+//! ```no_run
+//! use minecraft_world_trimmer::{Region, TrimPolicy};
+//! use std::path::Path;
+//!
+//! let mut region = Region::from_file_name(Path::new("world/region/r.0.0.mca")).unwrap();
+//! for (position, chunk) in region.iter_chunks() {
+//!     let inhabited_time = chunk.nbt().find_tag("InhabitedTime");
+//!     println!("{position:?}: {inhabited_time:?}");
+//! }
+//! let deleted_slots = TrimPolicy::default().apply(&mut region);
+//! println!("{} chunks deleted", deleted_slots.len());
+//! ```
+
+pub mod commands;
+pub mod nbt;
+mod rcon;
+mod region_loader;
+mod trim_policy;
+mod world;
+
+pub use region_loader::chunk_loader::chunk::{Chunk, ParseChunkError};
+pub use region_loader::chunk_loader::compression_scheme::CompressionScheme;
+pub use region_loader::chunk_loader::raw_chunk::RawChunk;
+pub use region_loader::external_chunk::ExternalChunk;
+pub use region_loader::linear::LinearError;
+pub use region_loader::location::Location;
+pub use region_loader::region::{
+    ParseRegionError, Region, RegionBytes, SlotConflictError, WriteRegionError,
+};
+pub use region_loader::region_format::RegionFormat;
+pub use region_loader::region_position::RegionPosition;
+pub use trim_policy::{Ticks, TrimPolicy};
+pub use world::area::Area;
+pub use world::server::{find_server_worlds, ServerLayoutError};
+pub use world::session_lock::SessionLockError;
@@ -1,24 +1,116 @@
 mod cli;
-mod commands;
-mod nbt;
-mod region_loader;
-mod world;
+mod config;
 
 use crate::cli::{Cli, Mode};
-use crate::commands::read::execute_read;
-use crate::commands::write::execute_write;
+use crate::config::Config;
 use clap::Parser;
 use flate2::Compression;
+use minecraft_world_trimmer::commands::{
+    execute_convert, execute_read, execute_recover, execute_restore, execute_write, RconOptions,
+    RestoreFilter, WriteOptions,
+};
+use minecraft_world_trimmer::{find_server_worlds, SessionLockError};
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-fn main() {
+/// Exit code when a world is open in the game or a server
+const WORLD_IN_USE_EXIT_CODE: u8 = 3;
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
-    let result = match cli.mode {
-        Mode::Write => execute_write(&cli.world_paths, Compression::new(cli.compression_level)),
-        Mode::Check => execute_read(&cli.world_paths),
-    };
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            match err.downcast_ref::<SessionLockError>() {
+                Some(SessionLockError::WorldInUse(_)) => ExitCode::from(WORLD_IN_USE_EXIT_CODE),
+                _ => ExitCode::FAILURE,
+            }
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let config = Config::load(cli)?;
+    // Only looked up by the modes working on worlds, print-config runs without any
+    let world_paths = || get_world_paths(&config);
+    let compression_scheme = config.compression.scheme.into();
+    let compression = Compression::new(config.compression.level);
+
+    match cli.mode {
+        Mode::PrintConfig => {
+            print!("{}", config.to_toml()?);
+            Ok(())
+        }
+        Mode::Write => execute_write(
+            &world_paths()?,
+            &WriteOptions {
+                dimensions: &config.dimensions,
+                compression_scheme,
+                compression,
+                recompress: config.compression.recompress,
+                quarantine_directory: config.quarantine_dir.as_deref(),
+                fix_misplaced: config.fix_misplaced,
+                backup_path: config.backup.as_deref(),
+                policy: config.trim.clone(),
+                rcon: config.rcon.as_ref().map(|rcon| RconOptions {
+                    address: &rcon.address,
+                    password: &rcon.password,
+                    loaded_radius: rcon.loaded_radius,
+                }),
+            },
+        ),
+        Mode::Check => execute_read(
+            &world_paths()?,
+            &config.dimensions,
+            config.quarantine_dir.as_deref(),
+            &config.trim,
+        ),
+        Mode::Recover => execute_recover(
+            &world_paths()?,
+            &config.dimensions,
+            compression_scheme,
+            compression,
+            config.backup.as_deref(),
+        ),
+        Mode::Convert => execute_convert(
+            &world_paths()?,
+            &config.dimensions,
+            cli.format.ok_or("convert mode requires `--format`")?.into(),
+            compression_scheme,
+            compression,
+        ),
+        Mode::Restore => execute_restore(
+            &world_paths()?,
+            config
+                .backup
+                .as_deref()
+                .ok_or("restore mode requires `--backup`, or `backup` in the configuration")?,
+            RestoreFilter {
+                dimensions: &config.dimensions,
+                area: cli.area,
+            },
+            cli.force,
+        ),
+    }
+}
+
+fn get_world_paths(config: &Config) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if config.worlds.is_empty() {
+        return Err("no world given, pass their paths or set `worlds` in the configuration".into());
+    }
+    if !config.server {
+        return Ok(config.worlds.clone());
+    }
 
-    if let Err(err) = result {
-        eprintln!("{err}");
+    let mut world_paths = Vec::new();
+    for server_root in &config.worlds {
+        for world in find_server_worlds(server_root)? {
+            println!("Found world {}", world.display());
+            world_paths.push(world);
+        }
     }
+    Ok(world_paths)
 }
@@ -1,29 +1,29 @@
-use std::string::FromUtf8Error;
+use crate::nbt::parse::ParseNbtError;
 
 macro_rules! impl_read_number {
     ($fn_name:ident, $type:ty) => {
-        pub fn $fn_name(&mut self) -> $type {
+        pub fn $fn_name(&mut self) -> Result<$type, ParseNbtError> {
             let size = std::mem::size_of::<$type>();
-            let bytes = &self.raw[self.index..self.index + size];
-            let integer = <$type>::from_be_bytes(bytes.try_into().unwrap());
-            self.index += size;
-            integer
+            let bytes = self.read_bytes(size)?;
+            Ok(<$type>::from_be_bytes(bytes.try_into().unwrap()))
         }
     };
 }
 
 macro_rules! impl_read_array {
     ($fn_name:ident, $type:ty, $reader:ident) => {
-        pub fn $fn_name(&mut self) -> Vec<$type> {
-            let size = self.read_i32() as usize;
-            let mut values = Vec::with_capacity(size);
+        pub fn $fn_name(&mut self) -> Result<Vec<$type>, ParseNbtError> {
+            let size = self.read_i32()?;
+            let size = usize::try_from(size).map_err(|_| ParseNbtError::InvalidLength(size))?;
+            // Do not trust the size for the allocation, the data might be corrupted
+            let mut values = Vec::with_capacity(size.min(self.remaining()));
 
             for _ in 0..size {
-                let next_tag = self.$reader();
+                let next_tag = self.$reader()?;
                 values.push(next_tag);
             }
 
-            values
+            Ok(values)
         }
     };
 }
@@ -38,21 +38,37 @@ impl<'a> BinaryReader<'a> {
         Self { raw, index: 0 }
     }
 
-    pub fn read_string(&mut self) -> Result<String, FromUtf8Error> {
-        let size = self.read_u16() as usize;
-        let bytes = &self.raw[self.index..self.index + size];
-        self.index += size;
-        String::from_utf8(bytes.to_vec())
+    pub fn read_string(&mut self) -> Result<String, ParseNbtError> {
+        let size = self.read_u16()? as usize;
+        let bytes = self.read_bytes(size)?;
+        Ok(String::from_utf8(bytes.to_vec())?)
     }
 
-    pub fn read_name(&mut self) -> Option<String> {
-        self.read_string().ok().filter(|s| !s.is_empty())
+    pub fn read_name(&mut self) -> Result<Option<String>, ParseNbtError> {
+        match self.read_string() {
+            Ok(name) => Ok(Some(name).filter(|s| !s.is_empty())),
+            Err(ParseNbtError::InvalidString(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
     }
 
-    pub fn read_type(&mut self) -> u8 {
+    pub fn read_type(&mut self) -> Result<u8, ParseNbtError> {
         self.read_u8()
     }
 
+    fn read_bytes(&mut self, size: usize) -> Result<&'a [u8], ParseNbtError> {
+        let bytes = self
+            .raw
+            .get(self.index..self.index + size)
+            .ok_or(ParseNbtError::UnexpectedEnd)?;
+        self.index += size;
+        Ok(bytes)
+    }
+
+    fn remaining(&self) -> usize {
+        self.raw.len().saturating_sub(self.index)
+    }
+
     impl_read_number!(read_i8, i8);
     impl_read_number!(read_u8, u8);
     impl_read_number!(read_i16, i16);
@@ -74,35 +90,35 @@ mod tests {
     fn test_read_i8() {
         let data = [0x7F];
         let mut reader = BinaryReader::new(&data);
-        assert_eq!(reader.read_i8(), 127);
+        assert_eq!(reader.read_i8().unwrap(), 127);
     }
 
     #[test]
     fn test_read_i16() {
         let data = [0x7F, 0xFF];
         let mut reader = BinaryReader::new(&data);
-        assert_eq!(reader.read_i16(), 32767);
+        assert_eq!(reader.read_i16().unwrap(), 32767);
     }
 
     #[test]
     fn test_read_u16() {
         let data = [0x0F, 0xFF];
         let mut reader = BinaryReader::new(&data);
-        assert_eq!(reader.read_u16(), 4095);
+        assert_eq!(reader.read_u16().unwrap(), 4095);
     }
 
     #[test]
     fn test_read_i32() {
         let data = [0x7F, 0xFF, 0xFF, 0xFF];
         let mut reader = BinaryReader::new(&data);
-        assert_eq!(reader.read_i32(), 2147483647);
+        assert_eq!(reader.read_i32().unwrap(), 2147483647);
     }
 
     #[test]
     fn test_read_f32() {
         let data = [0x3F, 0x80, 0x00, 0x00];
         let mut reader = BinaryReader::new(&data);
-        assert_eq!(reader.read_f32(), 1.0);
+        assert_eq!(reader.read_f32().unwrap(), 1.0);
     }
 
     #[test]
@@ -113,4 +129,15 @@ mod tests {
 
         assert_eq!(parsed, "HELLO");
     }
+
+    #[test]
+    fn test_unexpected_end() {
+        let data = [0, 5, 72, 69];
+        let mut reader = BinaryReader::new(&data);
+
+        assert!(matches!(
+            reader.read_string(),
+            Err(ParseNbtError::UnexpectedEnd)
+        ));
+    }
 }
@@ -1,13 +1,28 @@
 use crate::nbt::binary_reader::BinaryReader;
 use crate::nbt::parsers::parse_with_type::parse_with_type;
 use crate::nbt::tag::Tag;
+use std::string::FromUtf8Error;
+use thiserror::Error;
 
-pub fn parse_tag(reader: &mut BinaryReader) -> Tag {
-    let tag_type = reader.read_type();
+#[derive(Error, Debug)]
+pub enum ParseNbtError {
+    #[error("unexpected end of data")]
+    UnexpectedEnd,
+    #[error("invalid length {0}")]
+    InvalidLength(i32),
+    #[error("unsupported tag type {0}")]
+    UnsupportedTagType(u8),
+    #[error("invalid string")]
+    InvalidString(#[from] FromUtf8Error),
+}
+
+pub fn parse_tag(reader: &mut BinaryReader) -> Result<Tag, ParseNbtError> {
+    let tag_type = reader.read_type()?;
     parse_with_type(reader, tag_type, false)
 }
 
 #[cfg(test)]
+#[allow(clippy::excessive_precision)] // The values are written as stored in the test files
 mod tests {
     use super::*;
 
@@ -15,7 +30,7 @@ mod tests {
     fn test_hello_world() {
         let data = include_bytes!("../../test_files/hello_world.nbt");
         let mut reader = BinaryReader::new(data);
-        let result = parse_tag(&mut reader);
+        let result = parse_tag(&mut reader).unwrap();
 
         assert_eq!(
             result,
@@ -36,7 +51,7 @@ mod tests {
     fn test_bigtest() {
         let data = include_bytes!("../../test_files/bigtest.nbt");
         let mut reader = BinaryReader::new(data);
-        let result = parse_tag(&mut reader);
+        let result = parse_tag(&mut reader).unwrap();
 
         // Build the ByteArray
         let mut value = Vec::new();
@@ -177,4 +192,15 @@ mod tests {
         let serialized = result.to_bytes();
         assert_eq!(serialized, data);
     }
+
+    #[test]
+    fn test_truncated_data() {
+        let data = include_bytes!("../../test_files/bigtest.nbt");
+        let mut reader = BinaryReader::new(&data[..data.len() / 2]);
+
+        assert!(matches!(
+            parse_tag(&mut reader),
+            Err(ParseNbtError::UnexpectedEnd)
+        ));
+    }
 }
@@ -1,3 +1,5 @@
+use crate::nbt::binary_reader::BinaryReader;
+use crate::nbt::parse::{parse_tag, ParseNbtError};
 use crate::nbt::writers::{
     size_to_i32_bytes, write_array_i32, write_array_i64, write_array_i8, write_string,
 };
@@ -57,10 +59,22 @@ pub enum Tag {
 }
 
 impl Tag {
+    /// Parses uncompressed NBT data, such as a decompressed chunk or `level.dat`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseNbtError> {
+        parse_tag(&mut BinaryReader::new(bytes))
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.to_bytes_tag(false, false)
     }
 
+    pub fn get_byte(&self) -> Option<&i8> {
+        match self {
+            Tag::Byte { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
     pub fn get_long(&self) -> Option<&i64> {
         match self {
             Tag::Long { value, .. } => Some(value),
@@ -0,0 +1,178 @@
+//! A minimal client for the RCON protocol of Minecraft servers. Packets are laid out as follows,
+//! with little-endian integers:
+//! length (4 bytes) | request id (4 bytes) | type (4 bytes) | payload | two null bytes
+//! where the length counts every following byte.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use thiserror::Error;
+
+const LOGIN_TYPE: i32 = 3;
+const COMMAND_TYPE: i32 = 2;
+/// The server answers packets of an unknown type once it is done answering the previous command
+const END_MARKER_TYPE: i32 = 0;
+/// Payloads sent to the server cannot be longer, the ones it sends are at most 4096 bytes
+const MAX_PACKET_LENGTH: usize = 4096 + 10;
+
+pub struct RconClient {
+    stream: TcpStream,
+    next_request_id: i32,
+}
+
+impl RconClient {
+    /// Connects to the server and logs in. Commands time out after `timeout`, which has to be
+    /// long enough for the server to save the whole world.
+    pub fn connect(address: &str, password: &str, timeout: Duration) -> Result<Self, RconError> {
+        let stream = TcpStream::connect(address).map_err(RconError::Io)?;
+        stream
+            .set_read_timeout(Some(timeout))
+            .map_err(RconError::Io)?;
+        let mut client = Self {
+            stream,
+            next_request_id: 1,
+        };
+
+        let request_id = client.send(LOGIN_TYPE, password)?;
+        // The server answers with the request id -1 when the password is wrong
+        if read_packet(&mut client.stream)?.0 != request_id {
+            return Err(RconError::AuthenticationFailed);
+        }
+        Ok(client)
+    }
+
+    /// Runs the command on the server and returns its output. Outputs longer than 4096 bytes are
+    /// split across several packets, which are read until the answer to an end marker packet
+    /// sent right after the command.
+    pub fn command(&mut self, command: &str) -> Result<String, RconError> {
+        let request_id = self.send(COMMAND_TYPE, command)?;
+        let end_marker_id = self.send(END_MARKER_TYPE, "")?;
+        let mut output = Vec::new();
+        loop {
+            let (response_id, _, payload) = read_packet(&mut self.stream)?;
+            if response_id == end_marker_id {
+                return Ok(String::from_utf8_lossy(&output).to_string());
+            }
+            if response_id != request_id {
+                return Err(RconError::InvalidPacket);
+            }
+            output.extend(payload);
+        }
+    }
+
+    fn send(&mut self, packet_type: i32, payload: &str) -> Result<i32, RconError> {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        self.stream
+            .write_all(&encode_packet(request_id, packet_type, payload.as_bytes()))
+            .map_err(RconError::Io)?;
+        Ok(request_id)
+    }
+}
+
+pub fn encode_packet(request_id: i32, packet_type: i32, payload: &[u8]) -> Vec<u8> {
+    let length = (payload.len() + 10) as i32;
+    let mut packet = Vec::with_capacity(payload.len() + 14);
+    packet.extend(length.to_le_bytes());
+    packet.extend(request_id.to_le_bytes());
+    packet.extend(packet_type.to_le_bytes());
+    packet.extend(payload);
+    packet.extend([0, 0]);
+    packet
+}
+
+/// Reads a packet, returning its request id, type and payload
+pub fn read_packet(stream: &mut impl Read) -> Result<(i32, i32, Vec<u8>), RconError> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length).map_err(RconError::Io)?;
+    let length = i32::from_le_bytes(length) as usize;
+    if !(10..=MAX_PACKET_LENGTH).contains(&length) {
+        return Err(RconError::InvalidPacket);
+    }
+
+    let mut packet = vec![0; length];
+    stream.read_exact(&mut packet).map_err(RconError::Io)?;
+    let request_id = i32::from_le_bytes(packet[0..4].try_into().unwrap());
+    let packet_type = i32::from_le_bytes(packet[4..8].try_into().unwrap());
+    Ok((request_id, packet_type, packet[8..length - 2].to_vec()))
+}
+
+#[derive(Error, Debug)]
+pub enum RconError {
+    #[error("RCON connection failed: {0}")]
+    Io(std::io::Error),
+    #[error("RCON authentication failed, check the password")]
+    AuthenticationFailed,
+    #[error("invalid RCON packet")]
+    InvalidPacket,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// Starts a fake server on a random port, answering commands with `respond` in packets of at
+    /// most 4096 bytes like the game. Returns its address and the thread returning the commands
+    /// it received.
+    pub fn start_fake_server(
+        password: &'static str,
+        respond: impl Fn(&str) -> String + Send + 'static,
+    ) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut commands = Vec::new();
+            while let Ok((request_id, packet_type, payload)) = read_packet(&mut stream) {
+                let payload = String::from_utf8_lossy(&payload).to_string();
+                let response = if packet_type == LOGIN_TYPE {
+                    let response_id = if payload == password { request_id } else { -1 };
+                    encode_packet(response_id, COMMAND_TYPE, b"")
+                } else if packet_type == COMMAND_TYPE {
+                    let response = respond(&payload);
+                    commands.push(payload);
+                    let mut packets = response.as_bytes().chunks(4096).collect::<Vec<_>>();
+                    if packets.is_empty() {
+                        packets.push(b"");
+                    }
+                    packets
+                        .iter()
+                        .flat_map(|packet| encode_packet(request_id, 0, packet))
+                        .collect()
+                } else {
+                    let response = format!("Unknown request {packet_type:x}");
+                    encode_packet(request_id, 0, response.as_bytes())
+                };
+                stream.write_all(&response).unwrap();
+            }
+            commands
+        });
+
+        (address, handle)
+    }
+
+    #[test]
+    fn test_command() {
+        let (address, handle) = start_fake_server("secret", |command| match command {
+            "help" => "é".repeat(5000),
+            _ => format!("ran {command}"),
+        });
+
+        let mut client = RconClient::connect(&address, "secret", Duration::from_secs(5)).unwrap();
+        assert_eq!(client.command("list").unwrap(), "ran list");
+        // Long outputs are split across several packets
+        assert_eq!(client.command("help").unwrap(), "é".repeat(5000));
+        assert_eq!(client.command("list").unwrap(), "ran list");
+        drop(client);
+        assert_eq!(handle.join().unwrap(), vec!["list", "help", "list"]);
+
+        let (address, _) = start_fake_server("secret", |_| String::new());
+        assert!(matches!(
+            RconClient::connect(&address, "wrong", Duration::from_secs(5)),
+            Err(RconError::AuthenticationFailed)
+        ));
+    }
+}
@@ -1,37 +1,77 @@
 use crate::nbt::binary_reader::BinaryReader;
-use crate::nbt::parse::parse_tag;
+use crate::nbt::parse::{parse_tag, ParseNbtError};
 use crate::nbt::tag::Tag;
-use crate::region_loader::chunk_loader::compression_scheme::CompressionScheme;
+use crate::region_loader::chunk_loader::chunk_layout::ChunkLayout;
+use crate::region_loader::chunk_loader::compression_scheme::{CompressionScheme, EXTERNAL_FLAG};
+use crate::region_loader::chunk_loader::lz4_block;
 use crate::region_loader::get_u32::get_u32;
 use crate::region_loader::location::Location;
-use flate2::read::{GzDecoder, ZlibDecoder, ZlibEncoder};
+use crate::trim_policy::Ticks;
+use flate2::read::{GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder};
 use flate2::Compression;
 use std::io::Read;
+use std::path::Path;
+use thiserror::Error;
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Chunk {
-    pub nbt: Tag,
+    nbt: Tag,
     pub location: Location,
+    /// Index of the chunk in the location table it was read from
+    pub index: usize,
+    /// Whether the payload of the chunk was read from an external `.mcc` file
+    pub is_external: bool,
+    /// The compressed data as it was read, it is copied as-is when writing the chunk unless
+    /// recompression is requested. It is cleared when the NBT is modified.
+    payload: Option<Payload>,
 }
 
-impl Chunk {
-    const STATUS_FULL: &'static str = "minecraft:full";
+#[derive(PartialEq, Debug, Clone)]
+pub struct Payload {
+    pub compression_scheme: CompressionScheme,
+    pub bytes: Vec<u8>,
+}
 
-    pub fn from_location(buf: &[u8], location: Location) -> Result<Self, &'static str> {
+impl Chunk {
+    pub fn from_location(
+        buf: &[u8],
+        location: Location,
+        index: usize,
+        external_file: Option<&Path>,
+    ) -> Result<Self, ParseChunkError> {
         // Chunk header parsing
         // First get the chunk size in bytes
         let offset = location.get_offset() as usize;
+        let header_size = 5; // This can be a const
+        if buf.len() < offset + header_size {
+            return Err(ParseChunkError::OutOfBounds);
+        }
         let chunk_size = get_u32(buf, offset) as usize;
 
         // Then get the compression scheme
         let compression_scheme_index = offset + 4;
-        let compression_scheme = CompressionScheme::from_u8(buf[compression_scheme_index])?;
+        let compression_scheme_byte = buf[compression_scheme_index];
+        let is_external = compression_scheme_byte & EXTERNAL_FLAG != 0;
+        let compression_scheme =
+            CompressionScheme::from_u8(compression_scheme_byte & !EXTERNAL_FLAG).map_err(|_| {
+                ParseChunkError::UnsupportedCompressionScheme(compression_scheme_byte)
+            })?;
 
-        // Get the raw chunk data
-        let header_size = 5; // This can be a const
-        let start = offset + header_size;
-        let end = start + chunk_size - 1; // Remove 1 because the compression_scheme is included in the size
-        let raw_first_chunk = &buf[start..end];
+        // Get the raw chunk data, oversized chunks are stored in their own file next to the region
+        let external_bytes;
+        let raw_first_chunk = if is_external {
+            let external_file = external_file.ok_or(ParseChunkError::MissingExternalFile)?;
+            external_bytes =
+                std::fs::read(external_file).map_err(ParseChunkError::ExternalFileError)?;
+            &external_bytes[..]
+        } else {
+            let start = offset + header_size;
+            let end = start + chunk_size.saturating_sub(1); // Remove 1 because the compression_scheme is included in the size
+            if chunk_size == 0 || buf.len() < end {
+                return Err(ParseChunkError::OutOfBounds);
+            }
+            &buf[start..end]
+        };
 
         // Depending on the compression scheme, read the data
         let decoded_bytes = match compression_scheme {
@@ -45,34 +85,106 @@ impl Chunk {
                 let mut bytes = Vec::new();
                 decoder.read_to_end(&mut bytes).map(|_| bytes)
             }
+            CompressionScheme::None => Ok(raw_first_chunk.to_vec()),
+            CompressionScheme::Lz4 => lz4_block::decode(raw_first_chunk),
         };
 
         // Convert to string
-        let nbt = decoded_bytes
-            .map(|bytes| {
-                let mut binary_reader = BinaryReader::new(&bytes);
-                parse_tag(&mut binary_reader)
-            })
-            .map_err(|_| "Error while parsing NBT")?;
+        let decoded_bytes = decoded_bytes.map_err(ParseChunkError::DecompressionError)?;
+        let mut binary_reader = BinaryReader::new(&decoded_bytes);
+        let nbt = parse_tag(&mut binary_reader)?;
+
+        let payload = Payload {
+            compression_scheme,
+            bytes: raw_first_chunk.to_vec(),
+        };
 
-        Ok(Self { nbt, location })
+        Ok(Self {
+            nbt,
+            location,
+            index,
+            is_external,
+            payload: Some(payload),
+        })
     }
 
-    pub fn to_bytes(&self, compression: Compression) -> Vec<u8> {
+    /// Chunks of linear regions are stored uncompressed, so they have no payload to copy and are
+    /// always compressed again when written to an Anvil region
+    pub fn from_nbt_bytes(
+        bytes: &[u8],
+        location: Location,
+        index: usize,
+    ) -> Result<Self, ParseChunkError> {
+        let mut binary_reader = BinaryReader::new(bytes);
+        let nbt = parse_tag(&mut binary_reader)?;
+
+        Ok(Self {
+            nbt,
+            location,
+            index,
+            is_external: false,
+            payload: None,
+        })
+    }
+
+    /// Serializes the chunk with its length and compression scheme header. Chunks saved by a
+    /// version that cannot read the compression scheme keep their original payload, or are
+    /// compressed with zlib if they have none.
+    pub fn to_bytes(
+        &self,
+        compression_scheme: CompressionScheme,
+        compression: Compression,
+        recompress: bool,
+    ) -> Vec<u8> {
+        // Encoding the NBT again is expensive, skip it whenever the original data can be used
+        let is_supported = compression_scheme.is_supported_by(self.get_data_version());
+        if let (Some(payload), false) = (&self.payload, recompress && is_supported) {
+            return self.to_bytes_compression_scheme(payload.compression_scheme, &payload.bytes);
+        }
+        let compression_scheme = match is_supported {
+            true => compression_scheme,
+            false => CompressionScheme::Zlib,
+        };
+
         let decoded_bytes = self.nbt.to_bytes();
 
-        let mut encoder = ZlibEncoder::new(&decoded_bytes[..], compression);
-        let mut bytes = Vec::new();
-        if let Ok(encoded_bytes) = encoder.read_to_end(&mut bytes).map(|_| bytes) {
-            self.to_bytes_compression_scheme(CompressionScheme::Zlib, &encoded_bytes)
+        let encoded_bytes = match compression_scheme {
+            CompressionScheme::Gzip => {
+                let mut encoder = GzEncoder::new(&decoded_bytes[..], compression);
+                let mut bytes = Vec::new();
+                encoder.read_to_end(&mut bytes).map(|_| bytes)
+            }
+            CompressionScheme::Zlib => {
+                let mut encoder = ZlibEncoder::new(&decoded_bytes[..], compression);
+                let mut bytes = Vec::new();
+                encoder.read_to_end(&mut bytes).map(|_| bytes)
+            }
+            CompressionScheme::None => Ok(decoded_bytes.clone()),
+            CompressionScheme::Lz4 => Ok(lz4_block::encode(&decoded_bytes)),
+        };
+
+        if let Ok(encoded_bytes) = encoded_bytes {
+            self.to_bytes_compression_scheme(compression_scheme, &encoded_bytes)
         } else {
-            self.to_bytes_compression_scheme(CompressionScheme::Zlib, &decoded_bytes)
+            // Keep the chunk uncompressed rather than mislabeling its payload
+            self.to_bytes_compression_scheme(CompressionScheme::None, &decoded_bytes)
         }
     }
 
+    pub fn nbt(&self) -> &Tag {
+        &self.nbt
+    }
+
+    /// Replaces the NBT of the chunk, which is compressed again when written
+    pub fn set_nbt(&mut self, nbt: Tag) {
+        self.nbt = nbt;
+        self.payload = None;
+    }
+
     pub fn get_position(&self) -> Result<(i32, i32), &'static str> {
-        let x_pos_tag = self.nbt.find_tag("xPos").and_then(|v| v.get_int());
-        let z_pos_tag = self.nbt.find_tag("zPos").and_then(|v| v.get_int());
+        let data = self.get_data();
+        let x_pos_tag = data.find_tag("xPos").and_then(|v| v.get_int());
+        let z_pos_tag = data.find_tag("zPos").and_then(|v| v.get_int());
 
         match (x_pos_tag, z_pos_tag) {
             (Some(x), Some(z)) => Ok((*x, *z)),
@@ -80,29 +192,94 @@ impl Chunk {
         }
     }
 
-    /// Checks if a chunk is not fully generated or if it has never been inhabited
-    pub fn should_delete(&self) -> bool {
-        !self.is_fully_generated() || !self.has_been_inhabited()
+    /// Whether `to_bytes` compresses the chunk again rather than copying its original data
+    pub fn is_compressed_again(&self, recompress: bool) -> bool {
+        recompress || self.payload.is_none()
     }
 
-    fn is_fully_generated(&self) -> bool {
+    /// Returns the version of the game that saved the chunk, which chunks saved before 15w32a
+    /// do not have
+    pub fn get_data_version(&self) -> Option<i32> {
         self.nbt
-            .find_tag("Status")
-            .and_then(|tag| tag.get_string())
-            .map(|status| status == Chunk::STATUS_FULL)
-            .unwrap_or(false) // if the tag is not present, we can assume that the chunk is not fully generated
+            .find_tag("DataVersion")
+            .and_then(|tag| tag.get_int())
+            .copied()
     }
 
-    fn has_been_inhabited(&self) -> bool {
-        // The InhabitedTime value seems to be incremented for all 8 chunks around a player (including the one the player is standing in)
-        let inhabited_time = self
-            .nbt
+    /// Returns the layout of the chunk from its data version, if it is one this tool understands
+    fn get_layout(&self) -> Option<ChunkLayout> {
+        let has_level = matches!(self.nbt.find_tag("Level"), Some(Tag::Compound { .. }));
+        ChunkLayout::from_data_version(self.get_data_version(), has_level)
+    }
+
+    /// Checks if a chunk is not fully generated or if it has never been inhabited.
+    /// Chunks with a layout or status this tool does not understand are never deleted.
+    pub fn should_delete(&self) -> bool {
+        self.should_delete_with(Ticks::default())
+    }
+
+    /// Like [`Chunk::should_delete`], also deleting the chunks inhabited for less than
+    /// `min_inhabited_time`
+    pub fn should_delete_with(&self, min_inhabited_time: Ticks) -> bool {
+        let Some(layout) = self.get_layout() else {
+            return false;
+        };
+        match self.is_fully_generated(layout) {
+            Some(is_fully_generated) => {
+                !is_fully_generated || !self.has_been_inhabited(layout, min_inhabited_time)
+            }
+            None => false,
+        }
+    }
+
+    /// The number of ticks players spent near the chunk, if the chunk records it
+    pub fn get_inhabited_time(&self) -> Option<i64> {
+        self.get_data()
             .find_tag("InhabitedTime")
             .and_then(|tag| tag.get_long())
             .copied()
-            .unwrap_or(0); // If the tag is not present, we can assume that the chunk has never been inhabited
+    }
+
+    /// The game tick the chunk was last saved at, used to find the newest copy of a chunk
+    pub fn get_last_update(&self) -> i64 {
+        self.get_data()
+            .find_tag("LastUpdate")
+            .and_then(|tag| tag.get_long())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Chunks saved before 1.18 wrap their data in a `Level` compound
+    fn get_data(&self) -> &Tag {
+        match self.nbt.find_tag("Level") {
+            Some(level @ Tag::Compound { .. }) => level,
+            _ => &self.nbt,
+        }
+    }
 
-        inhabited_time > 0
+    fn is_fully_generated(&self, layout: ChunkLayout) -> Option<bool> {
+        let data = self.get_data();
+        match layout {
+            ChunkLayout::TerrainPopulated => data
+                .find_tag("TerrainPopulated")
+                .and_then(|tag| tag.get_byte())
+                .map(|populated| *populated != 0),
+            _ => data
+                .find_tag("Status")
+                .and_then(|tag| tag.get_string())
+                .and_then(|status| layout.is_full_status(status)),
+        }
+    }
+
+    fn has_been_inhabited(&self, layout: ChunkLayout, min_inhabited_time: Ticks) -> bool {
+        // The InhabitedTime value seems to be incremented for all 8 chunks around a player (including the one the player is standing in)
+        let Some(inhabited_time) = self.get_inhabited_time() else {
+            // InhabitedTime was added in 1.6, McRegion and early Anvil chunks only tell whether
+            // they have been populated. Otherwise, the chunk has never been inhabited.
+            return layout == ChunkLayout::TerrainPopulated;
+        };
+
+        inhabited_time > 0 && inhabited_time >= min_inhabited_time.0
     }
 
     fn to_bytes_compression_scheme(
@@ -117,3 +294,167 @@ impl Chunk {
         result
     }
 }
+
+#[derive(Error, Debug)]
+pub enum ParseChunkError {
+    #[error("chunk data is out of the bounds of the region file")]
+    OutOfBounds,
+    #[error("unsupported compression scheme {0}")]
+    UnsupportedCompressionScheme(u8),
+    #[error("no external file for this chunk")]
+    MissingExternalFile,
+    #[error("cannot read external chunk file: {0}")]
+    ExternalFileError(std::io::Error),
+    #[error("cannot decompress chunk: {0}")]
+    DecompressionError(std::io::Error),
+    #[error("cannot parse chunk NBT: {0}")]
+    NbtError(#[from] ParseNbtError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_with_level(data_version: i32, level: Vec<Tag>) -> Chunk {
+        Chunk {
+            nbt: Tag::Compound {
+                name: Some(String::new()),
+                value: vec![
+                    Tag::Int {
+                        name: Some(String::from("DataVersion")),
+                        value: data_version,
+                    },
+                    Tag::Compound {
+                        name: Some(String::from("Level")),
+                        value: level,
+                    },
+                ],
+            },
+            location: Location::new(8192, 4096, 0).unwrap(),
+            index: 0,
+            is_external: false,
+            payload: None,
+        }
+    }
+
+    fn inhabited_time(value: i64) -> Tag {
+        Tag::Long {
+            name: Some(String::from("InhabitedTime")),
+            value,
+        }
+    }
+
+    fn status(value: &str) -> Tag {
+        Tag::String {
+            name: Some(String::from("Status")),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_legacy_level_chunks() {
+        let populated = |value| Tag::Byte {
+            name: Some(String::from("TerrainPopulated")),
+            value,
+        };
+        assert!(!chunk_with_level(1343, vec![populated(1), inhabited_time(10)]).should_delete());
+        assert!(chunk_with_level(1343, vec![populated(0), inhabited_time(10)]).should_delete());
+
+        assert!(
+            !chunk_with_level(1631, vec![status("postprocessed"), inhabited_time(10)])
+                .should_delete()
+        );
+        assert!(
+            chunk_with_level(1631, vec![status("decorated"), inhabited_time(10)]).should_delete()
+        );
+
+        assert!(!chunk_with_level(2730, vec![status("full"), inhabited_time(10)]).should_delete());
+        assert!(chunk_with_level(2730, vec![status("full"), inhabited_time(0)]).should_delete());
+    }
+
+    #[test]
+    fn test_min_inhabited_time() {
+        let chunk = chunk_with_level(2730, vec![status("full"), inhabited_time(100)]);
+        assert!(!chunk.should_delete_with(Ticks(100)));
+        assert!(chunk.should_delete_with(Ticks(101)));
+        assert_eq!(chunk.get_inhabited_time(), Some(100));
+    }
+
+    #[test]
+    fn test_mcregion_chunks() {
+        let chunk = |populated| Chunk {
+            nbt: Tag::Compound {
+                name: Some(String::new()),
+                value: vec![Tag::Compound {
+                    name: Some(String::from("Level")),
+                    value: vec![Tag::Byte {
+                        name: Some(String::from("TerrainPopulated")),
+                        value: populated,
+                    }],
+                }],
+            },
+            location: Location::new(8192, 4096, 0).unwrap(),
+            index: 0,
+            is_external: false,
+            payload: None,
+        };
+
+        // Beta chunks have neither a data version nor an inhabited time
+        assert!(!chunk(1).should_delete());
+        assert!(chunk(0).should_delete());
+    }
+
+    #[test]
+    fn test_unsupported_compression_scheme() {
+        let mut chunk = chunk_with_level(3700, vec![status("full")]);
+        let compression = Compression::fast();
+        assert_eq!(
+            chunk.to_bytes(CompressionScheme::Lz4, compression, true)[4],
+            2
+        );
+
+        // The original payload is kept rather than compressed with zlib
+        let payload = chunk.to_bytes(CompressionScheme::Gzip, compression, true);
+        chunk.payload = Some(Payload {
+            compression_scheme: CompressionScheme::Gzip,
+            bytes: payload[5..].to_vec(),
+        });
+        assert_eq!(
+            chunk.to_bytes(CompressionScheme::None, compression, true),
+            payload
+        );
+
+        let chunk = chunk_with_level(3953, vec![status("full")]);
+        assert_eq!(
+            chunk.to_bytes(CompressionScheme::Lz4, compression, true)[4],
+            4
+        );
+    }
+
+    #[test]
+    fn test_unknown_layout_is_kept() {
+        // A pre-1.18 chunk with a 1.18 data version is not a layout this tool understands
+        assert!(!chunk_with_level(3953, vec![status("empty")]).should_delete());
+        assert!(
+            !chunk_with_level(2730, vec![status("unknown"), inhabited_time(0)]).should_delete()
+        );
+    }
+
+    #[test]
+    fn test_legacy_position() {
+        let chunk = chunk_with_level(
+            2730,
+            vec![
+                Tag::Int {
+                    name: Some(String::from("xPos")),
+                    value: -3,
+                },
+                Tag::Int {
+                    name: Some(String::from("zPos")),
+                    value: 7,
+                },
+            ],
+        );
+        assert_eq!(chunk.get_position(), Ok((-3, 7)));
+    }
+}
@@ -0,0 +1,153 @@
+/// How the data of a chunk is laid out, which changed several times across game versions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChunkLayout {
+    /// Before 1.13, data is wrapped in a `Level` compound and generation is tracked by the
+    /// `TerrainPopulated` byte
+    TerrainPopulated,
+    /// 1.13 wraps data in a `Level` compound and uses its own status names
+    LegacyStatus,
+    /// From 1.14 to 1.17, data is wrapped in a `Level` compound
+    LevelStatus,
+    /// Since 21w43a (1.18), data is stored at the root of the chunk
+    Status,
+}
+
+impl ChunkLayout {
+    /// Data version of 15w32a, the first version saving it. Lower values are not written by the
+    /// game.
+    const DATA_VERSION_15W32A: i32 = 100;
+    /// First data version of 1.13 snapshots (17w43a)
+    const DATA_VERSION_1_13: i32 = 1444;
+    /// First data version of 1.14 snapshots (18w43a)
+    const DATA_VERSION_1_14: i32 = 1901;
+    /// Data version of 21w43a, which removed the `Level` compound
+    const DATA_VERSION_1_18: i32 = 2844;
+
+    /// Chunks saved before 15w32a have no data version, they can only be recognized by their
+    /// `Level` compound
+    pub fn from_data_version(data_version: Option<i32>, has_level: bool) -> Option<Self> {
+        match (data_version, has_level) {
+            (None, true) => Some(ChunkLayout::TerrainPopulated),
+            (Some(version), _) if version < Self::DATA_VERSION_15W32A => None,
+            (Some(version), true) if version < Self::DATA_VERSION_1_13 => {
+                Some(ChunkLayout::TerrainPopulated)
+            }
+            (Some(version), true) if version < Self::DATA_VERSION_1_14 => {
+                Some(ChunkLayout::LegacyStatus)
+            }
+            (Some(version), true) if version < Self::DATA_VERSION_1_18 => {
+                Some(ChunkLayout::LevelStatus)
+            }
+            (Some(version), false) if version >= Self::DATA_VERSION_1_18 => {
+                Some(ChunkLayout::Status)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the given status means the chunk is fully generated, or `None` if the status does
+    /// not belong to this layout
+    pub fn is_full_status(self, status: &str) -> Option<bool> {
+        let status = status.strip_prefix("minecraft:").unwrap_or(status);
+        let (statuses, full_statuses): (&[&str], &[&str]) = match self {
+            ChunkLayout::TerrainPopulated => return None,
+            ChunkLayout::LegacyStatus => (
+                &[
+                    "empty",
+                    "base",
+                    "carved",
+                    "liquid_carved",
+                    "decorated",
+                    "lighted",
+                    "mobs_spawned",
+                    "finalized",
+                ],
+                &["fullchunk", "postprocessed"],
+            ),
+            ChunkLayout::LevelStatus | ChunkLayout::Status => (
+                &[
+                    "empty",
+                    "structure_starts",
+                    "structure_references",
+                    "biomes",
+                    "noise",
+                    "surface",
+                    "carvers",
+                    "liquid_carvers",
+                    "features",
+                    "initialize_light",
+                    "light",
+                    "spawn",
+                    "heightmaps",
+                ],
+                &["full"],
+            ),
+        };
+
+        if full_statuses.contains(&status) {
+            Some(true)
+        } else if statuses.contains(&status) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_data_version() {
+        assert_eq!(
+            ChunkLayout::from_data_version(None, true),
+            Some(ChunkLayout::TerrainPopulated)
+        );
+        assert_eq!(
+            ChunkLayout::from_data_version(Some(1343), true),
+            Some(ChunkLayout::TerrainPopulated)
+        );
+        assert_eq!(
+            ChunkLayout::from_data_version(Some(1631), true),
+            Some(ChunkLayout::LegacyStatus)
+        );
+        assert_eq!(
+            ChunkLayout::from_data_version(Some(2730), true),
+            Some(ChunkLayout::LevelStatus)
+        );
+        assert_eq!(
+            ChunkLayout::from_data_version(Some(3953), false),
+            Some(ChunkLayout::Status)
+        );
+        assert_eq!(ChunkLayout::from_data_version(None, false), None);
+        assert_eq!(ChunkLayout::from_data_version(Some(3953), true), None);
+        assert_eq!(ChunkLayout::from_data_version(Some(2730), false), None);
+        // Values the game never writes do not tell the layout
+        assert_eq!(ChunkLayout::from_data_version(Some(-1), true), None);
+        assert_eq!(ChunkLayout::from_data_version(Some(0), true), None);
+        assert_eq!(ChunkLayout::from_data_version(Some(i32::MIN), false), None);
+    }
+
+    #[test]
+    fn test_is_full_status() {
+        assert_eq!(
+            ChunkLayout::LegacyStatus.is_full_status("postprocessed"),
+            Some(true)
+        );
+        assert_eq!(
+            ChunkLayout::LegacyStatus.is_full_status("decorated"),
+            Some(false)
+        );
+        assert_eq!(ChunkLayout::LevelStatus.is_full_status("full"), Some(true));
+        assert_eq!(
+            ChunkLayout::Status.is_full_status("minecraft:full"),
+            Some(true)
+        );
+        assert_eq!(
+            ChunkLayout::Status.is_full_status("minecraft:features"),
+            Some(false)
+        );
+        assert_eq!(ChunkLayout::Status.is_full_status("postprocessed"), None);
+    }
+}
@@ -1,6 +1,15 @@
+/// Bit set on the compression scheme byte when the chunk payload is stored in an external `.mcc` file
+pub const EXTERNAL_FLAG: u8 = 128;
+
+/// Data version of 24w04a, the first version able to read uncompressed and LZ4 chunks
+const DATA_VERSION_24W04A: i32 = 3827;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CompressionScheme {
     Gzip,
     Zlib,
+    None,
+    Lz4,
 }
 
 impl CompressionScheme {
@@ -8,14 +17,29 @@ impl CompressionScheme {
         match byte {
             1 => Ok(CompressionScheme::Gzip),
             2 => Ok(CompressionScheme::Zlib),
+            3 => Ok(CompressionScheme::None),
+            4 => Ok(CompressionScheme::Lz4),
             _ => Err("Unsupported compression scheme"),
         }
     }
 
-    pub fn to_u8(&self) -> u8 {
+    pub fn to_u8(self) -> u8 {
         match self {
             CompressionScheme::Gzip => 1,
             CompressionScheme::Zlib => 2,
+            CompressionScheme::None => 3,
+            CompressionScheme::Lz4 => 4,
+        }
+    }
+
+    /// Whether the game version that saved a chunk can read chunks compressed with this scheme.
+    /// Chunks without a data version are older than any version reading LZ4.
+    pub fn is_supported_by(self, data_version: Option<i32>) -> bool {
+        match self {
+            CompressionScheme::Gzip | CompressionScheme::Zlib => true,
+            CompressionScheme::None | CompressionScheme::Lz4 => {
+                data_version.is_some_and(|version| version >= DATA_VERSION_24W04A)
+            }
         }
     }
 }
@@ -0,0 +1,164 @@
+//! The game compresses LZ4 chunks with `LZ4BlockOutputStream` from lz4-java, which splits the data
+//! in blocks, each one prefixed with the following header:
+//! magic (8 bytes) | token (1 byte) | compressed length | decompressed length | checksum
+//! where the last three fields are little-endian 32-bit integers.
+//! The stream ends with an empty block.
+
+use std::io::{Error, ErrorKind};
+use twox_hash::XxHash32;
+
+const MAGIC: &[u8; 8] = b"LZ4Block";
+const HEADER_LENGTH: usize = MAGIC.len() + 13;
+const BLOCK_SIZE: usize = 1 << 16;
+const COMPRESSION_METHOD_RAW: u8 = 0x10;
+const COMPRESSION_METHOD_LZ4: u8 = 0x20;
+/// Derived from the block size, as done by lz4-java
+const COMPRESSION_LEVEL: u8 = (BLOCK_SIZE.ilog2() - 10) as u8;
+const CHECKSUM_SEED: u32 = 0x9747b28c;
+/// LZ4 cannot shrink data further than this, a larger decompressed length means the header is
+/// corrupted
+const MAX_COMPRESSION_RATIO: usize = 255;
+
+pub fn decode(mut bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+
+    loop {
+        if bytes.len() < HEADER_LENGTH || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("Invalid LZ4 block header"));
+        }
+        let token = bytes[MAGIC.len()];
+        let compressed_length = read_u32_le(bytes, MAGIC.len() + 1) as usize;
+        let decompressed_length = read_u32_le(bytes, MAGIC.len() + 5) as usize;
+        let checksum = read_u32_le(bytes, MAGIC.len() + 9);
+        bytes = &bytes[HEADER_LENGTH..];
+
+        if compressed_length == 0 && decompressed_length == 0 {
+            return Ok(decoded);
+        }
+        if bytes.len() < compressed_length {
+            return Err(invalid_data("Truncated LZ4 block"));
+        }
+        let (block, remaining) = bytes.split_at(compressed_length);
+        bytes = remaining;
+
+        // Checked before allocating the block, the length is read from the data itself
+        let max_length = match token & 0xF0 {
+            COMPRESSION_METHOD_RAW => compressed_length,
+            _ => compressed_length * MAX_COMPRESSION_RATIO,
+        };
+        if decompressed_length > max_length.min(get_block_size(token)) {
+            return Err(invalid_data("Invalid LZ4 block length"));
+        }
+
+        let block = match token & 0xF0 {
+            COMPRESSION_METHOD_RAW => block.to_vec(),
+            COMPRESSION_METHOD_LZ4 => lz4_flex::block::decompress(block, decompressed_length)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err))?,
+            _ => return Err(invalid_data("Unsupported LZ4 block compression method")),
+        };
+        if block.len() != decompressed_length || compute_checksum(&block) != checksum {
+            return Err(invalid_data("Corrupted LZ4 block"));
+        }
+        decoded.extend(block);
+    }
+}
+
+pub fn encode(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bytes.len() / 2);
+
+    for block in bytes.chunks(BLOCK_SIZE) {
+        let compressed = lz4_flex::block::compress(block);
+        let (compression_method, data) = if compressed.len() < block.len() {
+            (COMPRESSION_METHOD_LZ4, &compressed[..])
+        } else {
+            (COMPRESSION_METHOD_RAW, block)
+        };
+        write_header(
+            &mut encoded,
+            compression_method,
+            data.len(),
+            block.len(),
+            compute_checksum(block),
+        );
+        encoded.extend_from_slice(data);
+    }
+
+    // The stream is terminated by an empty block
+    write_header(&mut encoded, COMPRESSION_METHOD_RAW, 0, 0, 0);
+    encoded
+}
+
+fn write_header(
+    buf: &mut Vec<u8>,
+    compression_method: u8,
+    compressed_length: usize,
+    decompressed_length: usize,
+    checksum: u32,
+) {
+    buf.extend_from_slice(MAGIC);
+    buf.push(compression_method | COMPRESSION_LEVEL);
+    buf.extend_from_slice(&(compressed_length as u32).to_le_bytes());
+    buf.extend_from_slice(&(decompressed_length as u32).to_le_bytes());
+    buf.extend_from_slice(&checksum.to_le_bytes());
+}
+
+/// lz4-java stores the base 2 logarithm of the block size minus 10 in the token, blocks never
+/// decompress to more than that
+fn get_block_size(token: u8) -> usize {
+    1 << ((token & 0x0F) + 10)
+}
+
+/// lz4-java only keeps the lowest 28 bits of the XXHash32 of the decompressed block
+fn compute_checksum(block: &[u8]) -> u32 {
+    XxHash32::oneshot(CHECKSUM_SEED, block) & 0x0FFFFFFF
+}
+
+fn read_u32_le(bytes: &[u8], index: usize) -> u32 {
+    u32::from_le_bytes(bytes[index..index + 4].try_into().unwrap())
+}
+
+fn invalid_data(message: &'static str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = (0..200_000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let encoded = encode(&data);
+
+        assert_eq!(&encoded[..MAGIC.len()], MAGIC);
+        assert_eq!(decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_empty_stream() {
+        let encoded = encode(&[]);
+
+        assert_eq!(encoded.len(), HEADER_LENGTH);
+        assert_eq!(encoded[MAGIC.len()], 0x16);
+        assert_eq!(decode(&encoded).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_corrupted_block() {
+        let data = b"Hello world! Hello world! Hello world!".to_vec();
+        let mut encoded = encode(&data);
+        encoded[MAGIC.len() + 9] ^= 1; // Alter the checksum
+
+        assert!(decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_corrupted_length() {
+        let data = (0..1000).map(|i| (i % 7) as u8).collect::<Vec<u8>>();
+        let mut encoded = encode(&data);
+        // A length this large must be refused rather than allocated
+        encoded[MAGIC.len() + 5..MAGIC.len() + 9].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(decode(&encoded).is_err());
+    }
+}
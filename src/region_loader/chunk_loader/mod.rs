@@ -1,2 +1,5 @@
 pub mod chunk;
-mod compression_scheme;
+pub mod chunk_layout;
+pub mod compression_scheme;
+mod lz4_block;
+pub mod raw_chunk;
@@ -0,0 +1,56 @@
+use crate::region_loader::chunk_loader::compression_scheme::{CompressionScheme, EXTERNAL_FLAG};
+use crate::region_loader::location::Location;
+
+/// A chunk that cannot be decoded, its sectors are kept exactly as they were read to be written
+/// back untouched
+#[derive(PartialEq, Debug, Clone)]
+pub struct RawChunk {
+    /// Index of the chunk in the location table
+    pub index: usize,
+    pub location: Location,
+    /// The sectors of the chunk, starting with its length and compression scheme header.
+    /// Empty if the location points past the end of the region file.
+    pub bytes: Vec<u8>,
+    /// Why the chunk could not be decoded
+    pub reason: String,
+    /// The end of the sectors, dropped when repairing the header as they overlap another chunk.
+    /// Only kept to be dumped in the quarantine directory.
+    pub dropped_bytes: Vec<u8>,
+}
+
+impl RawChunk {
+    pub fn from_location(buf: &[u8], location: Location, index: usize, reason: String) -> Self {
+        let offset = location.get_offset() as usize;
+        let end = (offset + location.get_size() as usize).min(buf.len());
+        let bytes = buf.get(offset..end).unwrap_or_default().to_vec();
+
+        Self {
+            index,
+            location,
+            bytes,
+            reason,
+            dropped_bytes: Vec::new(),
+        }
+    }
+
+    pub fn get_compression_scheme_byte(&self) -> Option<u8> {
+        self.bytes.get(4).copied()
+    }
+
+    /// Chunks compressed with a custom algorithm (eg. 127 since 24w05a) cannot be read by this tool,
+    /// while chunks with a known compression scheme that cannot be read are corrupted
+    pub fn has_known_compression_scheme(&self) -> bool {
+        self.get_compression_scheme_byte()
+            .is_none_or(|byte| CompressionScheme::from_u8(byte & !EXTERNAL_FLAG).is_ok())
+    }
+
+    /// The sectors as they were read from the region file, before the header was repaired
+    pub fn get_read_bytes(&self) -> Vec<u8> {
+        [&self.bytes[..], &self.dropped_bytes[..]].concat()
+    }
+
+    /// Whether nothing could be read for this chunk, in which case it cannot be written back
+    pub fn is_lost(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
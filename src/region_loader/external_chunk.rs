@@ -0,0 +1,69 @@
+use crate::region_loader::region_position::RegionPosition;
+use std::path::{Path, PathBuf};
+
+/// Chunks whose payload does not fit in 255 sectors are stored next to the region file in a
+/// `c.<x>.<z>.mcc` file, where `x` and `z` are the absolute chunk coordinates.
+#[derive(Debug, Clone)]
+pub struct ExternalChunks {
+    directory: PathBuf,
+    position: RegionPosition,
+    is_staged: bool,
+}
+
+impl ExternalChunks {
+    pub fn from_region_file_name(file_name: &Path) -> Option<Self> {
+        let position = RegionPosition::from_file_name(file_name)?;
+        let directory = file_name.parent()?.to_path_buf();
+        Some(Self {
+            directory,
+            position,
+            is_staged: false,
+        })
+    }
+
+    /// Reads the external files under their staged name, before the region file is written
+    pub fn staged(self) -> Self {
+        Self {
+            is_staged: true,
+            ..self
+        }
+    }
+
+    /// Returns the path of the external file for the chunk at the given index of the location table
+    pub fn get_path(&self, index: usize) -> PathBuf {
+        let (x, z) = self.position.get_chunk_position(index);
+        let path = self.directory.join(external_chunk_file_name(x, z));
+        match self.is_staged {
+            true => get_staged_path(&path),
+            false => path,
+        }
+    }
+}
+
+/// The compressed payload of a chunk that has to be written in its own `.mcc` file
+#[derive(Debug, PartialEq)]
+pub struct ExternalChunk {
+    pub x: i32,
+    pub z: i32,
+    pub bytes: Vec<u8>,
+}
+
+impl ExternalChunk {
+    pub fn file_name(&self) -> String {
+        external_chunk_file_name(self.x, self.z)
+    }
+}
+
+/// External files are written under this name, then renamed once the region file referencing them
+/// is written, so that the files of the original region are left untouched until then
+pub fn get_staged_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{file_name}.staged"))
+}
+
+fn external_chunk_file_name(x: i32, z: i32) -> String {
+    format!("c.{x}.{z}.mcc")
+}
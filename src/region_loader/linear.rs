@@ -0,0 +1,166 @@
+//! Linear (v1) region files, used by some server forks, store the whole region in a single zstd
+//! frame. The file is laid out as follows, with big-endian integers:
+//! superblock (8 bytes) | version (1 byte) | newest timestamp (8 bytes) | compression level (1 byte)
+//! | chunk count (2 bytes) | compressed length (4 bytes) | reserved (8 bytes) | zstd frame
+//! | superblock (8 bytes)
+//! Once decompressed, the frame holds the size and timestamp of the 1024 chunks followed by the
+//! uncompressed NBT of every chunk.
+
+use crate::region_loader::region::SlotConflictError;
+use thiserror::Error;
+
+const SUPERBLOCK: u64 = 0xC3FF13183CCA9D9A;
+const VERSION: u8 = 1;
+const HEADER_LENGTH: usize = 32;
+const FOOTER_LENGTH: usize = 8;
+const CHUNK_TABLE_LENGTH: usize = 1024 * 8;
+
+/// The uncompressed NBT of a chunk along with its slot in the region
+#[derive(Debug, PartialEq)]
+pub struct LinearChunk {
+    pub index: usize,
+    pub timestamp: u32,
+    pub bytes: Vec<u8>,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<LinearChunk>, LinearError> {
+    if bytes.len() < HEADER_LENGTH + FOOTER_LENGTH {
+        return Err(LinearError::Truncated);
+    }
+    if read_u64(bytes, 0) != SUPERBLOCK
+        || read_u64(bytes, bytes.len() - FOOTER_LENGTH) != SUPERBLOCK
+    {
+        return Err(LinearError::InvalidSuperblock);
+    }
+    let version = bytes[8];
+    if version != VERSION {
+        return Err(LinearError::UnsupportedVersion(version));
+    }
+    let compressed_length = read_u32(bytes, 20) as usize;
+    let compressed = bytes
+        .get(HEADER_LENGTH..HEADER_LENGTH + compressed_length)
+        .ok_or(LinearError::Truncated)?;
+
+    let decompressed = zstd::decode_all(compressed).map_err(LinearError::Decompression)?;
+    if decompressed.len() < CHUNK_TABLE_LENGTH {
+        return Err(LinearError::Truncated);
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = CHUNK_TABLE_LENGTH;
+    for index in 0..1024 {
+        let size = read_u32(&decompressed, index * 8) as usize;
+        let timestamp = read_u32(&decompressed, index * 8 + 4);
+        if size == 0 {
+            continue;
+        }
+        let chunk = decompressed
+            .get(offset..offset + size)
+            .ok_or(LinearError::Truncated)?;
+        chunks.push(LinearChunk {
+            index,
+            timestamp,
+            bytes: chunk.to_vec(),
+        });
+        offset += size;
+    }
+
+    Ok(chunks)
+}
+
+pub fn encode(chunks: &[LinearChunk], compression_level: i32) -> std::io::Result<Vec<u8>> {
+    let mut table = vec![0_u8; CHUNK_TABLE_LENGTH];
+    let mut sorted_chunks = chunks.iter().collect::<Vec<_>>();
+    sorted_chunks.sort_by_key(|chunk| chunk.index);
+
+    for chunk in &sorted_chunks {
+        let entry = chunk.index * 8;
+        table[entry..entry + 4].copy_from_slice(&(chunk.bytes.len() as u32).to_be_bytes());
+        table[entry + 4..entry + 8].copy_from_slice(&chunk.timestamp.to_be_bytes());
+    }
+    for chunk in &sorted_chunks {
+        table.extend_from_slice(&chunk.bytes);
+    }
+    let compressed = zstd::encode_all(&table[..], compression_level)?;
+
+    let newest_timestamp = chunks
+        .iter()
+        .map(|chunk| chunk.timestamp)
+        .max()
+        .unwrap_or(0);
+    let mut encoded = Vec::with_capacity(HEADER_LENGTH + compressed.len() + FOOTER_LENGTH);
+    encoded.extend(SUPERBLOCK.to_be_bytes());
+    encoded.push(VERSION);
+    encoded.extend((newest_timestamp as u64).to_be_bytes());
+    encoded.push(compression_level.clamp(i8::MIN as i32, i8::MAX as i32) as u8);
+    encoded.extend((chunks.len() as u16).to_be_bytes());
+    encoded.extend((compressed.len() as u32).to_be_bytes());
+    encoded.extend(0_u64.to_be_bytes());
+    encoded.extend(compressed);
+    encoded.extend(SUPERBLOCK.to_be_bytes());
+
+    Ok(encoded)
+}
+
+fn read_u32(bytes: &[u8], index: usize) -> u32 {
+    u32::from_be_bytes(bytes[index..index + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], index: usize) -> u64 {
+    u64::from_be_bytes(bytes[index..index + 8].try_into().unwrap())
+}
+
+#[derive(Error, Debug)]
+pub enum LinearError {
+    #[error("linear region file is truncated")]
+    Truncated,
+    #[error("invalid linear region superblock")]
+    InvalidSuperblock,
+    #[error("unsupported linear region version {0}")]
+    UnsupportedVersion(u8),
+    #[error("cannot decompress linear region: {0}")]
+    Decompression(std::io::Error),
+    #[error("cannot compress linear region: {0}")]
+    Compression(std::io::Error),
+    #[error("chunk at index {0} cannot be decoded and cannot be stored in a linear region")]
+    UnsupportedChunk(usize),
+    #[error(transparent)]
+    SlotConflict(#[from] SlotConflictError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let chunks = vec![
+            LinearChunk {
+                index: 3,
+                timestamp: 42,
+                bytes: vec![10, 0, 0, 0],
+            },
+            LinearChunk {
+                index: 1023,
+                timestamp: 7,
+                bytes: vec![10, 0, 0, 1, 2, 3, 0],
+            },
+        ];
+
+        let encoded = encode(&chunks, 3).unwrap();
+        assert_eq!(read_u64(&encoded, 0), SUPERBLOCK);
+        assert_eq!(read_u64(&encoded, 9), 42);
+        assert_eq!(decode(&encoded).unwrap(), chunks);
+    }
+
+    #[test]
+    fn test_invalid_superblock() {
+        let mut encoded = encode(&[], 3).unwrap();
+        let footer = encoded.len() - 1;
+        encoded[footer] ^= 0xFF;
+        assert!(matches!(
+            decode(&encoded),
+            Err(LinearError::InvalidSuperblock)
+        ));
+    }
+}
@@ -1,4 +1,9 @@
-mod chunk_loader;
+pub mod chunk_loader;
+pub mod external_chunk;
 mod get_u32;
-mod location;
+pub mod linear;
+pub mod location;
+pub mod recovery;
 pub mod region;
+pub mod region_format;
+pub mod region_position;
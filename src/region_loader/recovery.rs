@@ -0,0 +1,142 @@
+use crate::region_loader::chunk_loader::chunk::Chunk;
+use crate::region_loader::chunk_loader::compression_scheme::{CompressionScheme, EXTERNAL_FLAG};
+use crate::region_loader::external_chunk::ExternalChunks;
+use crate::region_loader::get_u32::get_u32;
+use crate::region_loader::location::Location;
+use crate::region_loader::region::get_slot;
+use crate::region_loader::region_position::RegionPosition;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The largest payload a chunk stored inside the region file can have
+const MAX_PAYLOAD_SIZE: usize = 255 * 4096;
+
+/// Scans the region file sector by sector for chunks, without relying on its location table.
+/// Chunks that do not belong to the region are ignored, and only the newest copy of each chunk is
+/// kept when several are found. The stubs of external chunks do not tell which chunk they belong
+/// to, so they are matched against the external files of the region.
+pub fn scan_chunks(
+    bytes: &[u8],
+    position: Option<RegionPosition>,
+    timestamp: u32,
+    external_chunks: Option<&ExternalChunks>,
+) -> Vec<Chunk> {
+    let mut chunks = HashMap::<usize, Chunk>::new();
+    let mut stub_offsets = Vec::new();
+    let mut offset = 8192;
+
+    while offset + 5 <= bytes.len() {
+        if is_external_stub(bytes, offset) {
+            stub_offsets.push(offset);
+            offset += 4096;
+            continue;
+        }
+        let Some(chunk) = read_chunk(bytes, offset, position, timestamp, None) else {
+            offset += 4096;
+            continue;
+        };
+
+        // Chunks never share sectors, so the sectors of this chunk can be skipped
+        let size = get_u32(bytes, offset) as usize + 4;
+        offset += size.div_ceil(4096) * 4096;
+        insert_newest(&mut chunks, chunk);
+    }
+
+    if let Some(external_chunks) = external_chunks.filter(|_| !stub_offsets.is_empty()) {
+        for index in 0..1024 {
+            let external_file = external_chunks.get_path(index);
+            if !external_file.exists() {
+                continue;
+            }
+            // The stub only gives the compression scheme of the external file
+            let chunk = stub_offsets.iter().find_map(|&offset| {
+                read_chunk(bytes, offset, position, timestamp, Some(&external_file))
+                    .filter(|chunk| get_slot(chunk) == index)
+            });
+            if let Some(chunk) = chunk {
+                insert_newest(&mut chunks, chunk);
+            }
+        }
+    }
+
+    let mut chunks = chunks.into_values().collect::<Vec<_>>();
+    chunks.sort_by_key(|chunk| chunk.index);
+    chunks
+}
+
+fn insert_newest(chunks: &mut HashMap<usize, Chunk>, mut chunk: Chunk) {
+    chunk.index = get_slot(&chunk);
+    match chunks.get(&chunk.index) {
+        Some(other) if other.get_last_update() >= chunk.get_last_update() => {}
+        _ => {
+            chunks.insert(chunk.index, chunk);
+        }
+    }
+}
+
+fn read_chunk(
+    bytes: &[u8],
+    offset: usize,
+    position: Option<RegionPosition>,
+    timestamp: u32,
+    external_file: Option<&Path>,
+) -> Option<Chunk> {
+    if external_file.is_none() && !has_chunk_header(bytes, offset) {
+        return None;
+    }
+
+    let location = Location::new(offset as u32, 4096, timestamp).ok()?;
+    let chunk = Chunk::from_location(bytes, location, 0, external_file).ok()?;
+    let (x, z) = chunk.get_position().ok()?;
+    let belongs_to_region = position
+        .is_none_or(|position| x.div_euclid(32) == position.x && z.div_euclid(32) == position.z);
+
+    belongs_to_region.then_some(chunk)
+}
+
+/// The sector of an external chunk only holds its length and compression scheme, its payload is
+/// in a `.mcc` file
+fn is_external_stub(bytes: &[u8], offset: usize) -> bool {
+    let compression_scheme = bytes[offset + 4];
+    get_u32(bytes, offset) == 1
+        && compression_scheme & EXTERNAL_FLAG != 0
+        && CompressionScheme::from_u8(compression_scheme & !EXTERNAL_FLAG).is_ok()
+}
+
+/// Checks the length and compression scheme of the sector, and the first bytes of the payload to
+/// avoid decompressing every sector
+fn has_chunk_header(bytes: &[u8], offset: usize) -> bool {
+    let size = get_u32(bytes, offset) as usize;
+    if !(2..=MAX_PAYLOAD_SIZE).contains(&size) || offset + 4 + size > bytes.len() {
+        return false;
+    }
+
+    let payload = &bytes[offset + 5..offset + 4 + size];
+    match bytes[offset + 4] {
+        1 => payload.starts_with(&[0x1f, 0x8b]),
+        2 => payload.starts_with(&[0x78]),
+        3 => payload.starts_with(&[10]),
+        4 => payload.starts_with(b"LZ4Block"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_destroyed_header() {
+        let mut bytes = include_bytes!("../../test_files/r.-1.-1.mca").to_vec();
+        bytes[..8192].fill(0);
+
+        let chunks = scan_chunks(&bytes, Some(RegionPosition { x: -1, z: -1 }), 0, None);
+        assert_eq!(chunks.len(), 6);
+        assert_eq!(chunks[0].get_position(), Ok((-32, -32)));
+        assert_eq!(chunks[0].index, 0);
+
+        // Chunks from other regions are ignored
+        let chunks = scan_chunks(&bytes, Some(RegionPosition { x: 0, z: 0 }), 0, None);
+        assert!(chunks.is_empty());
+    }
+}
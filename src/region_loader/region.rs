@@ -1,16 +1,60 @@
 use crate::region_loader::chunk_loader::chunk::Chunk;
+use crate::region_loader::chunk_loader::compression_scheme::{CompressionScheme, EXTERNAL_FLAG};
+use crate::region_loader::chunk_loader::raw_chunk::RawChunk;
+use crate::region_loader::external_chunk::{ExternalChunk, ExternalChunks};
 use crate::region_loader::get_u32::get_u32;
+use crate::region_loader::linear::{self, LinearChunk, LinearError};
 use crate::region_loader::location::Location;
+use crate::region_loader::region_format::RegionFormat;
+use crate::region_loader::region_position::RegionPosition;
 use flate2::Compression;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+/// The largest chunk that can be stored inside the region file, bigger chunks are stored in
+/// external `.mcc` files
+const MAX_CHUNK_SIZE: usize = 255 * 4096;
+
 #[derive(PartialEq, Debug)]
 pub struct Region {
     chunks: Vec<Chunk>,
+    /// Chunks that cannot be decoded, either because they are compressed with an unsupported
+    /// algorithm or because they are corrupted. They are never deleted.
+    raw_chunks: Vec<RawChunk>,
     is_modified: bool,
+    /// Whether invalid entries of the location table were dropped, in which case the header
+    /// must be rebuilt
+    is_header_repaired: bool,
+    external_files: Vec<PathBuf>,
+    /// The coordinates of the region, if known from its file name
+    position: Option<RegionPosition>,
+}
+
+/// The serialized region file along with the chunks that must be written to external files
+#[derive(Debug)]
+pub struct RegionBytes {
+    pub region: Vec<u8>,
+    pub external_chunks: Vec<ExternalChunk>,
+}
+
+/// Both the slot matching the position of a chunk and the slot it was read from hold other chunks
+#[derive(Error, Debug)]
+#[error("the chunk read from slot {0} cannot be stored, its slots are used by other chunks")]
+pub struct SlotConflictError(pub usize);
+
+/// The region cannot be serialized without losing one of its chunks
+#[derive(Error, Debug)]
+pub enum WriteRegionError {
+    #[error(transparent)]
+    SlotConflict(#[from] SlotConflictError),
+    /// Chunks too large for the region file need the position of the region to name their
+    /// external file
+    #[error("the chunk of slot {0} is too large to be stored in the region file")]
+    ChunkTooLarge(usize),
 }
 
 #[derive(Error, Debug)]
@@ -19,16 +63,66 @@ pub enum ParseRegionError {
     ReadError,
     #[error("cannot read header of region file")]
     HeaderError,
+    #[error("{0}")]
+    Linear(#[from] LinearError),
 }
 
 impl Region {
-    pub fn from_file_name(file_name: &PathBuf) -> Result<Self, ParseRegionError> {
+    /// Creates a region without any chunk, to receive chunks moved from other regions
+    pub fn new(position: RegionPosition) -> Self {
+        Self {
+            chunks: Vec::new(),
+            raw_chunks: Vec::new(),
+            is_modified: true,
+            is_header_repaired: false,
+            external_files: Vec::new(),
+            position: Some(position),
+        }
+    }
+
+    pub fn from_file_name(file_name: &Path) -> Result<Self, ParseRegionError> {
         let bytes = try_read_bytes(file_name).map_err(|_| ParseRegionError::ReadError)?;
-        Region::from_bytes(&bytes)
+        Region::from_file_bytes(&bytes, file_name)
+    }
+
+    /// Parses the bytes of a region file, in the format matching the extension of the file
+    fn from_file_bytes(bytes: &[u8], file_name: &Path) -> Result<Self, ParseRegionError> {
+        let mut region = match RegionFormat::from_file_name(file_name) {
+            RegionFormat::Anvil => {
+                let external_chunks = ExternalChunks::from_region_file_name(file_name);
+                Region::from_bytes(bytes, external_chunks.as_ref())?
+            }
+            RegionFormat::Linear => Region::from_linear_bytes(bytes)?,
+        };
+        region.position = RegionPosition::from_file_name(file_name);
+        Ok(region)
     }
 
-    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseRegionError> {
+    /// Checks that the serialized region, once written to `file_name`, can be read back without
+    /// losing any of the chunks of this region. External chunks are read from their staged files.
+    pub fn verify_bytes(&self, bytes: &[u8], file_name: &Path) -> bool {
+        let region = match RegionFormat::from_file_name(file_name) {
+            RegionFormat::Anvil => {
+                let external_chunks =
+                    ExternalChunks::from_region_file_name(file_name).map(ExternalChunks::staged);
+                Region::from_bytes(bytes, external_chunks.as_ref())
+            }
+            RegionFormat::Linear => Region::from_linear_bytes(bytes),
+        };
+        // Raw chunks are counted too, but a decoded chunk read back as a raw chunk is still lost
+        region.is_ok_and(|region| {
+            region.chunks.len() >= self.chunks.len()
+                && region.get_chunk_count() >= self.get_chunk_count()
+        })
+    }
+
+    fn from_bytes(
+        bytes: &[u8],
+        external_chunks: Option<&ExternalChunks>,
+    ) -> Result<Self, ParseRegionError> {
         let mut chunks = Vec::with_capacity(1024);
+        let mut raw_chunks = Vec::new();
+        let mut external_files = Vec::new();
         if bytes.len() < 8192 {
             return Err(ParseRegionError::HeaderError);
         }
@@ -42,53 +136,148 @@ impl Region {
             let location = Location::from_bytes(l, timestamp);
 
             if location.is_valid() {
-                if let Ok(chunk) = Chunk::from_location(bytes, location) {
-                    chunks.push(chunk);
+                let index = i / 4;
+                if location.get_offset() < 8192 {
+                    // Reading the chunk would read the header itself
+                    raw_chunks.push(RawChunk {
+                        index,
+                        location,
+                        bytes: Vec::new(),
+                        reason: String::from("location points inside the region header"),
+                        dropped_bytes: Vec::new(),
+                    });
+                    continue;
+                }
+                let external_file = external_chunks.map(|external| external.get_path(index));
+                match Chunk::from_location(bytes, location, index, external_file.as_deref()) {
+                    Ok(chunk) => {
+                        if chunk.is_external {
+                            external_files.extend(external_file);
+                        }
+                        chunks.push(chunk);
+                    }
+                    Err(err) => {
+                        // Chunks that cannot be decoded are kept as they are to never lose data
+                        let raw_chunk =
+                            RawChunk::from_location(bytes, location, index, err.to_string());
+                        raw_chunks.push(raw_chunk);
+                    }
+                }
+            }
+        }
+
+        let is_header_repaired = repair_header(bytes, &mut chunks, &mut raw_chunks);
+
+        Ok(Self {
+            chunks,
+            raw_chunks,
+            is_modified: is_header_repaired,
+            is_header_repaired,
+            external_files,
+            position: None,
+        })
+    }
+
+    fn from_linear_bytes(bytes: &[u8]) -> Result<Self, ParseRegionError> {
+        let mut chunks = Vec::with_capacity(1024);
+        let mut raw_chunks = Vec::new();
+
+        for linear_chunk in linear::decode(bytes)? {
+            let location = Location::from_bytes(0, linear_chunk.timestamp);
+            match Chunk::from_nbt_bytes(&linear_chunk.bytes, location, linear_chunk.index) {
+                Ok(chunk) => chunks.push(chunk),
+                Err(err) => {
+                    // Kept as an uncompressed Anvil payload so it can be written to either format
+                    let size = (linear_chunk.bytes.len() + 1) as u32;
+                    let mut bytes = Vec::from(size.to_be_bytes());
+                    bytes.push(CompressionScheme::None.to_u8());
+                    bytes.extend(linear_chunk.bytes);
+                    raw_chunks.push(RawChunk {
+                        index: linear_chunk.index,
+                        location,
+                        bytes,
+                        reason: err.to_string(),
+                        dropped_bytes: Vec::new(),
+                    });
                 }
-                // Else, we choose to not load the chunk and loose it because it is invalid
-                // FIXME: We might not want to loose the chunk if the compression scheme is an unsupported type (eg. LZ4 since 24w04a or custom algorithm since 24w05a)
             }
         }
 
         Ok(Self {
             chunks,
+            raw_chunks,
             is_modified: false,
+            is_header_repaired: false,
+            external_files: Vec::new(),
+            position: None,
         })
     }
 
-    pub fn to_bytes(&self, compression: Compression) -> Vec<u8> {
+    /// Serializes the region, chunks are copied with their original compressed data unless
+    /// `recompress` is set or they have been modified
+    pub fn to_bytes(
+        &self,
+        compression_scheme: CompressionScheme,
+        compression: Compression,
+        recompress: bool,
+    ) -> Result<RegionBytes, WriteRegionError> {
         let mut data = Vec::new();
+        let mut external_chunks = Vec::new();
         let mut location_table = [0_u8; 4096];
         let mut timestamp_table = [0_u8; 4096];
 
-        for chunk in &self.chunks {
+        for (chunk, slot) in self.chunks.iter().zip(self.assign_slots()?) {
             // Serialize the chunk to bytes
-            let mut serialized = chunk.to_bytes(compression);
+            let mut serialized = chunk.to_bytes(compression_scheme, compression, recompress);
+            // External files are found from the position of the region and the slot of the
+            // chunk when reading it back
+            let external_position = self
+                .position
+                .map(|position| position.get_chunk_position(slot))
+                .or_else(|| chunk.get_position().ok());
+
+            if serialized.len() > MAX_CHUNK_SIZE {
+                let (x, z) = external_position.ok_or(WriteRegionError::ChunkTooLarge(slot))?;
+                let bytes = split_external_payload(&mut serialized);
+                external_chunks.push(ExternalChunk { x, z, bytes });
+            }
             align_vec_size(&mut serialized);
 
             // Build the new location
             let new_position = (data.len() + 8192) as u32;
             let new_size = serialized.len() as u32;
             let original_timestamp = chunk.location.get_timestamp();
-            let new_location = Location::new(new_position, new_size, original_timestamp);
+            let new_location = Location::new(new_position, new_size, original_timestamp)
+                .map_err(|_| WriteRegionError::ChunkTooLarge(slot))?;
 
-            let chunk_position = chunk.get_position();
-            if let (Ok(new_location), Ok((x, z))) = (new_location, chunk_position) {
-                // Add the location to the header table
-                let position_in_table = get_position_in_table(x, z);
+            // Add the location to the header table
+            set_location(
+                &mut location_table,
+                &mut timestamp_table,
+                slot * 4,
+                new_location,
+            );
 
-                // Append to the location table
-                let location_bytes = new_location.to_location_bytes();
-                location_table[position_in_table..(4 + position_in_table)]
-                    .copy_from_slice(&location_bytes);
+            data.extend(serialized);
+        }
 
-                // Append to the timestamp table
-                let timestamp_bytes = new_location.to_timestamp_bytes();
-                timestamp_table[position_in_table..(4 + position_in_table)]
-                    .copy_from_slice(&timestamp_bytes);
-            }
-            // Else, the chunk is probably invalid, we can ignore it
-            // FIXME: We might not want to loose the corrupted chunk
+        for raw_chunk in self.iter_stored_raw_chunks() {
+            // Raw chunks are copied verbatim in their original slot
+            let mut serialized = raw_chunk.bytes.clone();
+            align_vec_size(&mut serialized);
+
+            let new_position = (data.len() + 8192) as u32;
+            let new_size = serialized.len() as u32;
+            let original_timestamp = raw_chunk.location.get_timestamp();
+
+            let new_location = Location::new(new_position, new_size, original_timestamp)
+                .map_err(|_| WriteRegionError::ChunkTooLarge(raw_chunk.index))?;
+            set_location(
+                &mut location_table,
+                &mut timestamp_table,
+                raw_chunk.index * 4,
+                new_location,
+            );
 
             data.extend(serialized);
         }
@@ -97,15 +286,98 @@ impl Region {
         result.extend_from_slice(&location_table);
         result.extend_from_slice(&timestamp_table);
         result.extend(data);
-        result
+
+        Ok(RegionBytes {
+            region: result,
+            external_chunks,
+        })
+    }
+
+    /// Serializes the region in the linear format. Raw chunks can only be stored if they are
+    /// uncompressed, as linear regions hold the uncompressed NBT of chunks.
+    pub fn to_linear_bytes(&self, compression: Compression) -> Result<Vec<u8>, LinearError> {
+        let mut linear_chunks = Vec::with_capacity(self.get_chunk_count());
+
+        for (chunk, index) in self.chunks.iter().zip(self.assign_slots()?) {
+            linear_chunks.push(LinearChunk {
+                index,
+                timestamp: chunk.location.get_timestamp(),
+                bytes: chunk.nbt().to_bytes(),
+            });
+        }
+
+        for raw_chunk in self.iter_stored_raw_chunks() {
+            let uncompressed = Some(CompressionScheme::None.to_u8());
+            if raw_chunk.get_compression_scheme_byte() != uncompressed {
+                return Err(LinearError::UnsupportedChunk(raw_chunk.index));
+            }
+            // The size includes the compression scheme byte, an empty payload would be stored as
+            // a missing chunk
+            let size = get_u32(&raw_chunk.bytes, 0) as usize;
+            if size < 2 || 4 + size > raw_chunk.bytes.len() {
+                return Err(LinearError::UnsupportedChunk(raw_chunk.index));
+            }
+            linear_chunks.push(LinearChunk {
+                index: raw_chunk.index,
+                timestamp: raw_chunk.location.get_timestamp(),
+                bytes: raw_chunk.bytes[5..4 + size].to_vec(),
+            });
+        }
+
+        linear::encode(&linear_chunks, compression.level() as i32).map_err(LinearError::Compression)
+    }
+
+    /// Returns the slot each chunk is written to, in the order of the chunks. Chunks in their own
+    /// slot keep it, then the other chunks take the slot matching their position, or else the slot
+    /// they were read from. A chunk is never written over the slot of another chunk.
+    fn assign_slots(&self) -> Result<Vec<usize>, SlotConflictError> {
+        let mut used_slots = self
+            .iter_stored_raw_chunks()
+            .map(|chunk| chunk.index)
+            .collect::<HashSet<_>>();
+
+        let mut slots = vec![None; self.chunks.len()];
+        for (slot, chunk) in slots.iter_mut().zip(&self.chunks) {
+            if is_in_own_slot(chunk) && used_slots.insert(chunk.index) {
+                *slot = Some(chunk.index);
+            }
+        }
+        for (slot, chunk) in slots.iter_mut().zip(&self.chunks) {
+            if slot.is_none() {
+                let free_slot = [get_slot(chunk), chunk.index]
+                    .into_iter()
+                    .find(|&index| used_slots.insert(index))
+                    .ok_or(SlotConflictError(chunk.index))?;
+                *slot = Some(free_slot);
+            }
+        }
+        Ok(slots.into_iter().flatten().collect())
     }
 
     pub fn get_chunks(&self) -> &Vec<Chunk> {
         &self.chunks
     }
 
+    /// Iterates over the decoded chunks along with their absolute chunk coordinates, read from the
+    /// chunk itself or else from the position of the region and the slot of the chunk. Chunks
+    /// without coordinates, such as the chunks of a region file that was renamed, give `None`.
+    pub fn iter_chunks(&self) -> impl Iterator<Item = (Option<(i32, i32)>, &Chunk)> {
+        self.chunks.iter().map(|chunk| {
+            let position = chunk.get_position().ok().or_else(|| {
+                self.position
+                    .map(|position| position.get_chunk_position(chunk.index))
+            });
+            (position, chunk)
+        })
+    }
+
+    pub fn get_raw_chunks(&self) -> &Vec<RawChunk> {
+        &self.raw_chunks
+    }
+
+    /// Counts the chunks that are written back, lost chunks have no data left to write
     pub fn get_chunk_count(&self) -> usize {
-        self.chunks.len()
+        self.chunks.len() + self.iter_stored_raw_chunks().count()
     }
 
     pub fn remove_chunk_by_index(&mut self, index: usize) {
@@ -115,25 +387,239 @@ impl Region {
         }
     }
 
+    /// Removes the chunk at the given absolute chunk coordinates
+    pub fn take_chunk(&mut self, x: i32, z: i32) -> Option<Chunk> {
+        let i = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.get_position() == Ok((x, z)))?;
+        self.is_modified = true;
+        Some(self.chunks.remove(i))
+    }
+
+    /// Adds a chunk in the slot matching its position, the chunk is given back if the slot is
+    /// already used
+    pub fn insert_chunk(&mut self, mut chunk: Chunk) -> Result<(), Chunk> {
+        let Ok((x, z)) = chunk.get_position() else {
+            return Err(chunk);
+        };
+        let index = get_position_in_table(x, z) / 4;
+        let is_slot_used = self.chunks.iter().any(|other| get_slot(other) == index)
+            || self.raw_chunks.iter().any(|other| other.index == index);
+        if is_slot_used {
+            return Err(chunk);
+        }
+
+        chunk.index = index;
+        self.chunks.push(chunk);
+        self.is_modified = true;
+        Ok(())
+    }
+
+    /// Adds the chunks found by scanning the sectors of the region. A recovered chunk replaces
+    /// the chunk in the same slot if it is newer, or a corrupted chunk, but never a chunk
+    /// compressed with an unsupported algorithm. Returns the number of recovered chunks.
+    pub fn merge_recovered_chunks(
+        &mut self,
+        recovered_chunks: Vec<Chunk>,
+        external_chunks: Option<&ExternalChunks>,
+    ) -> usize {
+        let mut recovered_count = 0;
+        // Lost chunks have nothing left to recover from the location table
+        self.raw_chunks.retain(|raw_chunk| !raw_chunk.is_lost());
+
+        for mut chunk in recovered_chunks {
+            let index = get_slot(&chunk);
+            if let Some(i) = self
+                .chunks
+                .iter()
+                .position(|other| get_slot(other) == index)
+            {
+                if chunk.get_last_update() > self.chunks[i].get_last_update() {
+                    chunk.index = self.chunks[i].index;
+                    self.chunks[i] = chunk;
+                    recovered_count += 1;
+                }
+                continue;
+            }
+            if let Some(i) = self.raw_chunks.iter().position(|raw| raw.index == index) {
+                if !self.raw_chunks[i].has_known_compression_scheme() {
+                    continue;
+                }
+                self.raw_chunks.remove(i);
+            }
+
+            chunk.index = index;
+            self.chunks.push(chunk);
+            recovered_count += 1;
+        }
+
+        // Listed like the external files read with the location table, to be backed up and
+        // replaced when the region is saved
+        if let Some(external_chunks) = external_chunks {
+            for chunk in self.chunks.iter().filter(|chunk| chunk.is_external) {
+                let external_file = external_chunks.get_path(chunk.index);
+                if !self.external_files.contains(&external_file) {
+                    self.external_files.push(external_file);
+                }
+            }
+        }
+
+        if recovered_count > 0 {
+            self.is_modified = true;
+        }
+        recovered_count
+    }
+
+    /// Forces the region to be written, so chunks are placed in the slot matching their position
+    pub fn mark_modified(&mut self) {
+        self.is_modified = true;
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.chunks.is_empty()
+        self.chunks.is_empty() && self.iter_stored_raw_chunks().next().is_none()
+    }
+
+    fn iter_stored_raw_chunks(&self) -> impl Iterator<Item = &RawChunk> {
+        self.raw_chunks.iter().filter(|chunk| !chunk.is_lost())
     }
 
     pub fn is_modified(&self) -> bool {
         self.is_modified
     }
+
+    pub fn is_header_repaired(&self) -> bool {
+        self.is_header_repaired
+    }
+
+    /// Returns the `.mcc` files the chunks of this region were read from
+    pub fn get_external_files(&self) -> &Vec<PathBuf> {
+        &self.external_files
+    }
+}
+
+/// Drops the entries of the location table that do not point to their own chunk: duplicated
+/// entries, entries pointing inside the sectors of another chunk and entries pointing past the end
+/// of the file. Returns whether the header has to be rebuilt.
+fn repair_header(bytes: &[u8], chunks: &mut Vec<Chunk>, raw_chunks: &mut [RawChunk]) -> bool {
+    let mut is_repaired = raw_chunks.iter().any(|raw_chunk| raw_chunk.is_lost());
+
+    // Entries sharing the same sectors decode to the same chunk, the entry matching the position
+    // of the chunk is kept
+    let mut chunk_by_offset = HashMap::new();
+    let mut duplicated_chunks = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        match chunk_by_offset.entry(chunk.location.get_offset()) {
+            Entry::Vacant(entry) => {
+                entry.insert(i);
+            }
+            Entry::Occupied(mut entry) => {
+                if is_in_own_slot(chunk) && !is_in_own_slot(&chunks[*entry.get()]) {
+                    duplicated_chunks.push(entry.insert(i));
+                } else {
+                    duplicated_chunks.push(i);
+                }
+            }
+        }
+    }
+    duplicated_chunks.sort_unstable();
+    for &i in duplicated_chunks.iter().rev() {
+        chunks.remove(i);
+        is_repaired = true;
+    }
+
+    // The sectors actually used by each chunk, from the length of its payload as the sector
+    // count of the header may be wrong
+    let mut used_sectors = Vec::with_capacity(chunks.len());
+    for chunk in chunks.iter() {
+        let offset = chunk.location.get_offset();
+        let length = get_u32(bytes, offset as usize) + 4;
+        let size = length.div_ceil(4096) * 4096;
+        if size > chunk.location.get_size() {
+            is_repaired = true;
+        }
+        used_sectors.push((offset..offset + size, chunk.index));
+    }
+
+    for raw_chunk in raw_chunks
+        .iter_mut()
+        .filter(|raw_chunk| !raw_chunk.is_lost())
+    {
+        let start = raw_chunk.location.get_offset();
+        if let Some((_, index)) = used_sectors
+            .iter()
+            .find(|(sectors, _)| sectors.contains(&start))
+        {
+            raw_chunk.dropped_bytes = std::mem::take(&mut raw_chunk.bytes);
+            raw_chunk.reason = format!("location overlaps the chunk stored at index {index}");
+            is_repaired = true;
+        } else if let Some(next_start) = used_sectors
+            .iter()
+            .map(|(sectors, _)| sectors.start)
+            .filter(|&offset| offset > start)
+            .min()
+        {
+            // Only keep the sectors up to the next chunk
+            let max_length = (next_start - start) as usize;
+            if raw_chunk.bytes.len() > max_length {
+                raw_chunk.dropped_bytes = raw_chunk.bytes.split_off(max_length);
+                is_repaired = true;
+            }
+        }
+    }
+
+    is_repaired
+}
+
+/// Chunks without position, such as entities chunks, are assumed to be in their own slot
+fn is_in_own_slot(chunk: &Chunk) -> bool {
+    get_slot(chunk) == chunk.index
+}
+
+/// The slot of the chunk in the location table, from its position if it has one
+pub fn get_slot(chunk: &Chunk) -> usize {
+    chunk
+        .get_position()
+        .map(|(x, z)| get_position_in_table(x, z) / 4)
+        .unwrap_or(chunk.index)
 }
 
 fn align_vec_size(vec: &mut Vec<u8>) {
-    let aligned_size = ((vec.len() + 4095) / 4096) * 4096;
+    let aligned_size = vec.len().div_ceil(4096) * 4096;
     vec.resize(aligned_size, 0);
 }
 
+/// Replaces the serialized chunk with a stub pointing to its external file and returns the
+/// compressed payload to store in that file
+fn split_external_payload(serialized: &mut Vec<u8>) -> Vec<u8> {
+    let payload = serialized.split_off(5);
+    let compression_scheme = serialized[4] | EXTERNAL_FLAG;
+    serialized.clear();
+    serialized.extend(1_u32.to_be_bytes()); // Only the compression scheme byte is left
+    serialized.push(compression_scheme);
+    payload
+}
+
 fn get_position_in_table(x: i32, z: i32) -> usize {
     (4 * ((x & 31) + (z & 31) * 32)) as usize
 }
 
-fn try_read_bytes(file_path: &PathBuf) -> std::io::Result<Vec<u8>> {
+fn set_location(
+    location_table: &mut [u8; 4096],
+    timestamp_table: &mut [u8; 4096],
+    position_in_table: usize,
+    location: Location,
+) {
+    // Append to the location table
+    let location_bytes = location.to_location_bytes();
+    location_table[position_in_table..(4 + position_in_table)].copy_from_slice(&location_bytes);
+
+    // Append to the timestamp table
+    let timestamp_bytes = location.to_timestamp_bytes();
+    timestamp_table[position_in_table..(4 + position_in_table)].copy_from_slice(&timestamp_bytes);
+}
+
+fn try_read_bytes(file_path: &Path) -> std::io::Result<Vec<u8>> {
     let mut buf = Vec::<u8>::new();
     File::open(file_path).and_then(|mut file| file.read_to_end(&mut buf))?;
     Ok(buf)
@@ -142,6 +628,8 @@ fn try_read_bytes(file_path: &PathBuf) -> std::io::Result<Vec<u8>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nbt::tag::Tag;
+    use crate::region_loader::recovery::scan_chunks;
 
     #[test]
     fn test_align_vec_size() {
@@ -163,15 +651,18 @@ mod tests {
         let original_bytes = include_bytes!("../../test_files/r.-1.-1.mca");
 
         // Parse the region file
-        let original_parsed_region_file = Region::from_bytes(original_bytes).unwrap();
-        let serialized_bytes = original_parsed_region_file.to_bytes(Compression::fast());
+        let original_parsed_region_file = Region::from_bytes(original_bytes, None).unwrap();
+        let serialized_bytes = original_parsed_region_file
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), true)
+            .unwrap()
+            .region;
 
         // Wa cannot validate the header as the compression and chunk order in the payload may differ
         // resulting in a modification of the offset bytes, so as long as the re-parsed region file is
         // the same as the parsed original, we should be fine
 
         // Try parsing again the serialized region file and check if both still have the same chunk data
-        let parsed_again = Region::from_bytes(&serialized_bytes).unwrap();
+        let parsed_again = Region::from_bytes(&serialized_bytes, None).unwrap();
 
         let original_chunks = original_parsed_region_file.get_chunks();
         let parsed_chunks = parsed_again.get_chunks();
@@ -182,7 +673,452 @@ mod tests {
             let original_chunk = &original_chunks[i];
             let parsed_chunk = &parsed_chunks[i];
             // We cannot check for equality on the location since it might have different offset and size
-            assert_eq!(original_chunk.nbt, parsed_chunk.nbt);
+            assert_eq!(original_chunk.nbt(), parsed_chunk.nbt());
+        }
+    }
+
+    #[test]
+    fn test_iter_chunks() {
+        let region = Region::from_file_name(Path::new("test_files/r.-1.-1.mca")).unwrap();
+        assert_eq!(region.iter_chunks().count(), region.get_chunks().len());
+        for (position, chunk) in region.iter_chunks() {
+            let (x, z) = position.unwrap();
+            assert_eq!((x.div_euclid(32), z.div_euclid(32)), (-1, -1));
+            let nbt = Tag::from_bytes(&chunk.nbt().to_bytes()).unwrap();
+            assert_eq!(&nbt, chunk.nbt());
+        }
+    }
+
+    #[test]
+    fn test_compression_schemes() {
+        let original_bytes = include_bytes!("../../test_files/r.-1.-1.mca");
+        let region = Region::from_bytes(original_bytes, None).unwrap();
+
+        for compression_scheme in [
+            CompressionScheme::Gzip,
+            CompressionScheme::Zlib,
+            CompressionScheme::None,
+            CompressionScheme::Lz4,
+        ] {
+            let serialized_bytes = region
+                .to_bytes(compression_scheme, Compression::fast(), true)
+                .unwrap()
+                .region;
+            let parsed_again = Region::from_bytes(&serialized_bytes, None).unwrap();
+
+            assert_eq!(parsed_again.get_chunk_count(), region.get_chunk_count());
+            for (original_chunk, parsed_chunk) in region.chunks.iter().zip(&parsed_again.chunks) {
+                assert_eq!(original_chunk.nbt(), parsed_chunk.nbt());
+            }
+        }
+    }
+
+    #[test]
+    fn test_external_chunk() {
+        // Random data does not compress, making the chunk larger than 255 sectors
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let noise = (0..160_000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as i64
+            })
+            .collect();
+        let nbt = Tag::Compound {
+            name: None,
+            value: Vec::from([
+                Tag::Int {
+                    name: Some(String::from("xPos")),
+                    value: -3,
+                },
+                Tag::Int {
+                    name: Some(String::from("zPos")),
+                    value: -5,
+                },
+                Tag::LongArray {
+                    name: Some(String::from("noise")),
+                    value: noise,
+                },
+            ]),
+        };
+        let location = Location::new(8192, 4096, 0).unwrap();
+        let chunk = Chunk::from_nbt_bytes(&nbt.to_bytes(), location, 0).unwrap();
+        let region = Region {
+            chunks: Vec::from([chunk]),
+            raw_chunks: Vec::new(),
+            is_modified: true,
+            is_header_repaired: false,
+            external_files: Vec::new(),
+            position: None,
+        };
+
+        let serialized = region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), true)
+            .unwrap();
+        assert_eq!(serialized.region.len(), 8192 + 4096);
+        assert_eq!(serialized.external_chunks.len(), 1);
+
+        let external_chunk = &serialized.external_chunks[0];
+        assert_eq!(external_chunk.file_name(), "c.-3.-5.mcc");
+
+        // Write both files next to each other to read the chunk back from the external file
+        let temp_dir = tempfile::tempdir().unwrap();
+        let directory = temp_dir.path().to_path_buf();
+        std::fs::create_dir_all(&directory).unwrap();
+        let region_file_name = directory.join("r.-1.-1.mca");
+        std::fs::write(&region_file_name, &serialized.region).unwrap();
+        std::fs::write(
+            directory.join(external_chunk.file_name()),
+            &external_chunk.bytes,
+        )
+        .unwrap();
+
+        let parsed = Region::from_file_name(&region_file_name);
+        let parsed = parsed.unwrap();
+
+        assert_eq!(
+            parsed.get_external_files(),
+            &Vec::from([directory.join("c.-3.-5.mcc")])
+        );
+        assert_eq!(parsed.get_chunks()[0].nbt(), region.get_chunks()[0].nbt());
+
+        // Once the location table is lost, the stub is matched against the external file
+        let mut damaged_bytes = serialized.region.clone();
+        damaged_bytes[..8192].fill(0);
+        let external_chunks = ExternalChunks::from_region_file_name(&region_file_name);
+        let position = Some(RegionPosition { x: -1, z: -1 });
+        let recovered_chunks = scan_chunks(&damaged_bytes, position, 0, external_chunks.as_ref());
+        let mut damaged_region = Region::from_bytes(&damaged_bytes, None).unwrap();
+        assert_eq!(
+            damaged_region.merge_recovered_chunks(recovered_chunks, external_chunks.as_ref()),
+            1
+        );
+        assert_eq!(
+            damaged_region.get_external_files(),
+            parsed.get_external_files()
+        );
+        assert_eq!(
+            damaged_region.get_chunks()[0].nbt(),
+            region.get_chunks()[0].nbt()
+        );
+
+        // The file is named after the slot the chunk is stored in, not after its own coordinates
+        let region = Region {
+            position: Some(RegionPosition { x: 0, z: 0 }),
+            ..region
+        };
+        let serialized = region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), true)
+            .unwrap();
+        assert_eq!(serialized.external_chunks[0].file_name(), "c.29.27.mcc");
+
+        // Without any position, the external file cannot be named
+        let nbt = Tag::Compound {
+            name: None,
+            value: Vec::from([nbt.find_tag("noise").unwrap().clone()]),
+        };
+        let chunk = Chunk::from_nbt_bytes(&nbt.to_bytes(), location, 0).unwrap();
+        let region = Region {
+            chunks: Vec::from([chunk]),
+            position: None,
+            ..region
+        };
+        assert!(matches!(
+            region.to_bytes(CompressionScheme::Zlib, Compression::fast(), true),
+            Err(WriteRegionError::ChunkTooLarge(0))
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_compression_scheme() {
+        // A single chunk at index 5 compressed with a custom algorithm
+        let mut original_bytes = vec![0_u8; 8192 + 4096];
+        original_bytes[20..24].copy_from_slice(&[0, 0, 2, 1]);
+        original_bytes[4096 + 20..4096 + 24].copy_from_slice(&[0, 0, 0, 42]);
+        let payload = [0, 0, 0, 8, 127, 0, 3, b'a', b':', b'b', 1, 2];
+        original_bytes[8192..8192 + payload.len()].copy_from_slice(&payload);
+
+        let region = Region::from_bytes(&original_bytes, None).unwrap();
+        assert!(region.get_chunks().is_empty());
+        assert_eq!(region.get_raw_chunks().len(), 1);
+        assert_eq!(region.get_raw_chunks()[0].index, 5);
+        assert!(!region.is_empty());
+
+        // The chunk must be written back untouched in the same slot
+        let serialized = region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), true)
+            .unwrap();
+        assert_eq!(serialized.region, original_bytes);
+    }
+
+    #[test]
+    fn test_corrupted_chunk() {
+        let mut original_bytes = include_bytes!("../../test_files/r.-1.-1.mca").to_vec();
+        // Alter the compressed data of the first chunk
+        for byte in &mut original_bytes[8192 + 20..8192 + 40] {
+            *byte = !*byte;
+        }
+
+        let region = Region::from_bytes(&original_bytes, None).unwrap();
+        assert_eq!(region.get_raw_chunks().len(), 1);
+        let raw_chunk = &region.get_raw_chunks()[0];
+        assert_eq!(raw_chunk.index, 0);
+        assert!(raw_chunk.has_known_compression_scheme());
+
+        // The corrupted chunk must be kept in its original slot
+        let serialized_bytes = region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), true)
+            .unwrap()
+            .region;
+        let parsed_again = Region::from_bytes(&serialized_bytes, None).unwrap();
+        assert_eq!(parsed_again.get_chunk_count(), region.get_chunk_count());
+        assert_eq!(parsed_again.get_raw_chunks()[0].index, 0);
+        assert_eq!(parsed_again.get_raw_chunks()[0].bytes, raw_chunk.bytes);
+    }
+
+    #[test]
+    fn test_linear_region() {
+        let original_bytes = include_bytes!("../../test_files/r.-1.-1.mca");
+        let region = Region::from_bytes(original_bytes, None).unwrap();
+
+        let linear_bytes = region.to_linear_bytes(Compression::fast()).unwrap();
+        let linear_region = Region::from_linear_bytes(&linear_bytes).unwrap();
+        assert_eq!(linear_region.get_chunk_count(), region.get_chunk_count());
+
+        // Converting back to Anvil compresses the chunks again
+        let serialized_bytes = linear_region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), false)
+            .unwrap()
+            .region;
+        let parsed_again = Region::from_bytes(&serialized_bytes, None).unwrap();
+        for (original_chunk, parsed_chunk) in region.chunks.iter().zip(&parsed_again.chunks) {
+            assert_eq!(original_chunk.nbt(), parsed_chunk.nbt());
+            assert_eq!(original_chunk.index, parsed_chunk.index);
+            assert_eq!(
+                original_chunk.location.get_timestamp(),
+                parsed_chunk.location.get_timestamp()
+            );
+        }
+    }
+
+    #[test]
+    fn test_header_repair() {
+        let mut original_bytes = include_bytes!("../../test_files/r.-1.-1.mca").to_vec();
+        // Duplicate the entry of the first chunk in another slot
+        original_bytes.copy_within(0..4, 400);
+        // Point one entry inside the header and another one past the end of the file
+        original_bytes[800..804].copy_from_slice(&[0, 0, 1, 1]);
+        original_bytes[1200..1204].copy_from_slice(&[0, 1, 0, 1]);
+        // Point another entry in the middle of the sectors of the first chunk
+        original_bytes[2000..2004].copy_from_slice(&[0, 0, 3, 1]);
+
+        let region = Region::from_bytes(&original_bytes, None).unwrap();
+        assert!(region.is_header_repaired());
+        assert!(region.is_modified());
+        assert_eq!(region.get_chunks().len(), 6);
+        assert_eq!(region.get_chunks()[0].index, 0);
+        assert_eq!(region.get_raw_chunks().len(), 3);
+        assert!(region.get_raw_chunks().iter().all(|chunk| chunk.is_lost()));
+        // The overlapping sectors are still available to the quarantine
+        assert_eq!(
+            region.get_raw_chunks()[2].get_read_bytes(),
+            original_bytes[3 * 4096..4 * 4096]
+        );
+        assert_eq!(region.get_chunk_count(), 6);
+
+        let serialized_bytes = region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), false)
+            .unwrap()
+            .region;
+        let parsed_again = Region::from_bytes(&serialized_bytes, None).unwrap();
+        assert!(!parsed_again.is_header_repaired());
+        assert_eq!(parsed_again.get_chunk_count(), 6);
+    }
+
+    #[test]
+    fn test_move_chunk() {
+        let original_bytes = include_bytes!("../../test_files/r.-1.-1.mca");
+        let mut region = Region::from_bytes(original_bytes, None).unwrap();
+        let mut target_region = Region::new(RegionPosition { x: -1, z: -1 });
+
+        let chunk = region.take_chunk(-2, -1).unwrap();
+        assert!(region.is_modified());
+        assert!(region.take_chunk(-2, -1).is_none());
+
+        // The chunk is given back when its slot is already used
+        target_region.insert_chunk(chunk.clone()).unwrap();
+        assert_eq!(target_region.get_chunks()[0].index, 1022);
+        assert_eq!(target_region.insert_chunk(chunk.clone()), Err(chunk));
+    }
+
+    #[test]
+    fn test_slot_conflict() {
+        let original_bytes = include_bytes!("../../test_files/r.-1.-1.mca");
+        let mut region = Region::from_bytes(original_bytes, None).unwrap();
+        let moved_chunk = region.chunks.pop().unwrap();
+        let own_chunk = region.chunks[0].clone();
+
+        // A chunk read from the slot of another chunk is listed first, the chunk in its own slot
+        // keeps it while the other one moves to its own free slot
+        let mut misplaced_chunk = moved_chunk.clone();
+        misplaced_chunk.index = own_chunk.index;
+        region.chunks.insert(0, misplaced_chunk);
+        let serialized_bytes = region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), false)
+            .unwrap()
+            .region;
+        let parsed_again = Region::from_bytes(&serialized_bytes, None).unwrap();
+        assert_eq!(parsed_again.get_chunk_count(), region.chunks.len());
+        assert!(parsed_again.chunks.iter().all(is_in_own_slot));
+
+        // A copy that cannot be stored in either slot is refused instead of replacing a chunk
+        let mut stale_chunk = region.chunks[1].clone();
+        stale_chunk.index = region.chunks[2].index;
+        region.chunks.insert(1, stale_chunk);
+        assert!(matches!(
+            region.to_bytes(CompressionScheme::Zlib, Compression::fast(), false),
+            Err(WriteRegionError::SlotConflict(SlotConflictError(index)))
+                if index == region.chunks[3].index
+        ));
+        assert!(matches!(
+            region.to_linear_bytes(Compression::fast()),
+            Err(LinearError::SlotConflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_linear_raw_chunk_size() {
+        let raw_chunk = |size: u32| {
+            let mut bytes = Vec::from(size.to_be_bytes());
+            bytes.push(CompressionScheme::None.to_u8());
+            bytes.resize(4096, 0);
+            RawChunk {
+                index: 3,
+                location: Location::new(8192, 4096, 0).unwrap(),
+                bytes,
+                reason: String::from("cannot parse chunk NBT"),
+                dropped_bytes: Vec::new(),
+            }
+        };
+        let region = |raw_chunk| Region {
+            chunks: Vec::new(),
+            raw_chunks: vec![raw_chunk],
+            is_modified: true,
+            is_header_repaired: false,
+            external_files: Vec::new(),
+            position: None,
+        };
+
+        // Sizes without any payload, or past the sectors of the chunk, cannot be stored
+        for size in [0, 1, 4096] {
+            assert!(matches!(
+                region(raw_chunk(size)).to_linear_bytes(Compression::fast()),
+                Err(LinearError::UnsupportedChunk(3))
+            ));
+        }
+        assert!(region(raw_chunk(2))
+            .to_linear_bytes(Compression::fast())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_recover_newest_chunk() {
+        let original_bytes = include_bytes!("../../test_files/r.-1.-1.mca");
+        let mut region = Region::from_bytes(original_bytes, None).unwrap();
+
+        // An older copy is ignored while a newer one replaces the chunk
+        let mut older_chunk = region.get_chunks()[0].clone();
+        let mut newer_chunk = older_chunk.clone();
+        for (chunk, last_update) in [(&mut older_chunk, 0), (&mut newer_chunk, 5000)] {
+            let mut nbt = chunk.nbt().clone();
+            if let Tag::Compound { value, .. } = &mut nbt {
+                for tag in value.iter_mut() {
+                    if let Tag::Long { name, value } = tag {
+                        if name.as_deref() == Some("LastUpdate") {
+                            *value = last_update;
+                        }
+                    }
+                }
+            }
+            chunk.set_nbt(nbt);
+        }
+
+        assert_eq!(region.merge_recovered_chunks(vec![older_chunk], None), 0);
+        assert!(!region.is_modified());
+        assert_eq!(region.merge_recovered_chunks(vec![newer_chunk], None), 1);
+        assert_eq!(region.get_chunks()[0].get_last_update(), 5000);
+        assert_eq!(region.get_chunk_count(), 6);
+
+        let serialized_bytes = region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), false)
+            .unwrap()
+            .region;
+        let parsed_again = Region::from_bytes(&serialized_bytes, None).unwrap();
+        assert!(parsed_again
+            .get_chunks()
+            .iter()
+            .any(|chunk| chunk.get_last_update() == 5000));
+    }
+
+    #[test]
+    fn test_passthrough_payload() {
+        let original_bytes = include_bytes!("../../test_files/r.-1.-1.mca");
+        let region = Region::from_bytes(original_bytes, None).unwrap();
+
+        // The compression scheme is ignored when chunks are not recompressed
+        let serialized_bytes = region
+            .to_bytes(CompressionScheme::Lz4, Compression::fast(), false)
+            .unwrap()
+            .region;
+        let parsed_again = Region::from_bytes(&serialized_bytes, None).unwrap();
+
+        for (original_chunk, parsed_chunk) in region.chunks.iter().zip(&parsed_again.chunks) {
+            let bytes = parsed_chunk.to_bytes(CompressionScheme::Lz4, Compression::fast(), false);
+            assert_eq!(bytes[4], CompressionScheme::Zlib.to_u8());
+            assert_eq!(
+                bytes,
+                original_chunk.to_bytes(CompressionScheme::Lz4, Compression::fast(), false)
+            );
         }
     }
+
+    #[test]
+    fn test_verify_bytes() {
+        let original_bytes = include_bytes!("../../test_files/r.-1.-1.mca");
+        let region = Region::from_bytes(original_bytes, None).unwrap();
+        let file_name = Path::new("r.-1.-1.mca");
+
+        let serialized_bytes = region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), false)
+            .unwrap()
+            .region;
+        assert!(region.verify_bytes(&serialized_bytes, file_name));
+
+        // A truncated write loses the chunks at the end of the file
+        let truncated_bytes = &serialized_bytes[..serialized_bytes.len() - 4096];
+        assert!(!region.verify_bytes(truncated_bytes, file_name));
+        assert!(!region.verify_bytes(&[], file_name));
+
+        // A raw chunk left out of the header is lost as well
+        let mut region = region;
+        let chunk = region.chunks.pop().unwrap();
+        let raw_chunk = RawChunk {
+            index: chunk.index,
+            location: chunk.location,
+            bytes: chunk.to_bytes(CompressionScheme::Zlib, Compression::fast(), false),
+            reason: String::from("unknown"),
+            dropped_bytes: Vec::new(),
+        };
+        region.raw_chunks.push(raw_chunk);
+        assert!(!region.verify_bytes(&serialized_bytes[..8192], file_name));
+        let serialized_bytes = region
+            .to_bytes(CompressionScheme::Zlib, Compression::fast(), false)
+            .unwrap()
+            .region;
+        assert!(region.verify_bytes(&serialized_bytes, file_name));
+        let mut without_raw_chunk = serialized_bytes.clone();
+        without_raw_chunk[chunk.index * 4..chunk.index * 4 + 4].fill(0);
+        assert!(!region.verify_bytes(&without_raw_chunk, file_name));
+    }
 }
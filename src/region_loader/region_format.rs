@@ -0,0 +1,26 @@
+use std::path::Path;
+
+/// The container format of a region file, picked from its extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionFormat {
+    /// Sector based region files, `.mca` and legacy McRegion `.mcr` files
+    Anvil,
+    /// zstd compressed `.linear` region files
+    Linear,
+}
+
+impl RegionFormat {
+    pub fn from_file_name(file_name: &Path) -> Self {
+        match file_name.extension().and_then(|ext| ext.to_str()) {
+            Some("linear") => RegionFormat::Linear,
+            _ => RegionFormat::Anvil,
+        }
+    }
+
+    pub fn get_extension(self) -> &'static str {
+        match self {
+            RegionFormat::Anvil => "mca",
+            RegionFormat::Linear => "linear",
+        }
+    }
+}
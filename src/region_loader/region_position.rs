@@ -0,0 +1,53 @@
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RegionPosition {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl RegionPosition {
+    /// Parses the region coordinates from a file name such as `r.<x>.<z>.mca`
+    pub fn from_file_name(file_name: &Path) -> Option<Self> {
+        let file_name = file_name.file_name()?.to_str()?;
+        let mut parts = file_name.split('.');
+
+        if parts.next()? != "r" {
+            return None;
+        }
+        let x = parts.next()?.parse::<i32>().ok()?;
+        let z = parts.next()?.parse::<i32>().ok()?;
+
+        Some(Self { x, z })
+    }
+
+    /// Returns the absolute chunk coordinates of the chunk stored at the given index of the
+    /// location table
+    pub fn get_chunk_position(&self, index: usize) -> (i32, i32) {
+        let index = index as i32;
+        (self.x * 32 + (index & 31), self.z * 32 + (index >> 5))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_from_file_name() {
+        let position = RegionPosition::from_file_name(&PathBuf::from("world/region/r.-1.2.mca"));
+        assert_eq!(position, Some(RegionPosition { x: -1, z: 2 }));
+
+        let invalid = RegionPosition::from_file_name(&PathBuf::from("world/region/c.1.2.mcc"));
+        assert_eq!(invalid, None);
+    }
+
+    #[test]
+    fn test_get_chunk_position() {
+        let position = RegionPosition { x: -1, z: -1 };
+        assert_eq!(position.get_chunk_position(0), (-32, -32));
+        assert_eq!(position.get_chunk_position(1023), (-1, -1));
+        assert_eq!(position.get_chunk_position(33), (-31, -31));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::region_loader::chunk_loader::chunk::Chunk;
+use crate::region_loader::region::Region;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Decides which chunks of a region are deleted
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct TrimPolicy {
+    /// Chunks inhabited for less time are deleted. Chunks that have never been inhabited are
+    /// always deleted.
+    pub min_inhabited_time: Ticks,
+    /// Overrides `min_inhabited_time` for the dimensions with these namespaced ids
+    pub dimension_min_inhabited_time: BTreeMap<String, Ticks>,
+}
+
+impl TrimPolicy {
+    /// Returns the policy applying to the chunks of the dimension
+    pub fn for_dimension(&self, dimension: &str) -> TrimPolicy {
+        TrimPolicy {
+            min_inhabited_time: self.get_min_inhabited_time(dimension),
+            dimension_min_inhabited_time: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_min_inhabited_time(&self, dimension: &str) -> Ticks {
+        self.dimension_min_inhabited_time
+            .get(dimension)
+            .copied()
+            .unwrap_or(self.min_inhabited_time)
+    }
+
+    /// Whether the chunk is not fully generated or has been inhabited for less than the minimum
+    /// inhabited time. Chunks with a layout or status this tool does not understand are never
+    /// deleted.
+    pub fn should_delete(&self, chunk: &Chunk) -> bool {
+        chunk.should_delete_with(self.min_inhabited_time)
+    }
+
+    /// Removes the chunks to delete from the region, returning the slots they were stored in so
+    /// that the matching entities and POI chunks can be deleted too
+    pub fn apply(&self, region: &mut Region) -> HashSet<usize> {
+        let chunks_to_delete_indices: Vec<_> = region
+            .get_chunks()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, chunk)| self.should_delete(chunk).then_some(i))
+            .collect();
+
+        let deleted_indices = chunks_to_delete_indices
+            .iter()
+            .map(|&i| region.get_chunks()[i].index)
+            .collect::<HashSet<_>>();
+        for &index in chunks_to_delete_indices.iter().rev() {
+            region.remove_chunk_by_index(index);
+        }
+        deleted_indices
+    }
+}
+
+/// Written like the `--min-inhabited-time` flags, such as `5m minecraft:the_nether=30s`
+impl Display for TrimPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.min_inhabited_time)?;
+        for (dimension, min_inhabited_time) in &self.dimension_min_inhabited_time {
+            write!(f, " {dimension}={min_inhabited_time}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A duration in game ticks, written as a number of ticks or with a unit such as `30s`, `5m`,
+/// `2h` or `1d`. The game runs 20 ticks per second.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Ticks(pub i64);
+
+const UNITS: [(&str, i64); 5] = [
+    ("d", 24 * 60 * 60 * 20),
+    ("h", 60 * 60 * 20),
+    ("m", 60 * 20),
+    ("s", 20),
+    ("t", 1),
+];
+
+impl FromStr for Ticks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (value, ticks_per_unit) = UNITS
+            .iter()
+            .find_map(|(unit, ticks)| s.strip_suffix(unit).map(|value| (value, *ticks)))
+            .unwrap_or((s, 1));
+        value
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|value| *value >= 0)
+            .and_then(|value| value.checked_mul(ticks_per_unit))
+            .map(Ticks)
+            .ok_or_else(|| {
+                format!("invalid time `{s}`, expected a number of ticks or a duration such as `5m`")
+            })
+    }
+}
+
+impl Display for Ticks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match UNITS
+            .iter()
+            .find(|(_, ticks)| self.0 != 0 && self.0 % ticks == 0)
+        {
+            Some((unit, ticks)) => write!(f, "{}{unit}", self.0 / ticks),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl Serialize for Ticks {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Ticks {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum TicksValue {
+            Ticks(i64),
+            Duration(String),
+        }
+
+        match TicksValue::deserialize(deserializer)? {
+            TicksValue::Ticks(ticks) if ticks >= 0 => Ok(Ticks(ticks)),
+            TicksValue::Ticks(ticks) => Err(serde::de::Error::custom(format!(
+                "invalid time `{ticks}`, it cannot be negative"
+            ))),
+            TicksValue::Duration(duration) => duration.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_apply() {
+        let mut region = Region::from_file_name(Path::new("test_files/r.-1.-1.mca")).unwrap();
+        let policy = TrimPolicy::default();
+        let chunk_count = region.get_chunks().len();
+        let to_delete = region
+            .get_chunks()
+            .iter()
+            .filter(|chunk| policy.should_delete(chunk))
+            .count();
+
+        let deleted_indices = policy.apply(&mut region);
+        assert_eq!(deleted_indices.len(), to_delete);
+        assert_eq!(region.get_chunks().len(), chunk_count - to_delete);
+        assert!(!region
+            .get_chunks()
+            .iter()
+            .any(|chunk| deleted_indices.contains(&chunk.index)));
+        assert_eq!(region.is_modified(), to_delete > 0);
+    }
+
+    #[test]
+    fn test_ticks() {
+        assert_eq!("5m".parse(), Ok(Ticks(6000)));
+        assert_eq!("120".parse(), Ok(Ticks(120)));
+        assert_eq!("1d".parse(), Ok(Ticks(1_728_000)));
+        assert!("-5s".parse::<Ticks>().is_err());
+        assert!("5 minutes".parse::<Ticks>().is_err());
+
+        assert_eq!(Ticks(6000).to_string(), "5m");
+        assert_eq!(Ticks(90 * 20).to_string(), "90s");
+        assert_eq!(Ticks(25).to_string(), "25t");
+        assert_eq!(Ticks(0).to_string(), "0");
+    }
+
+    #[test]
+    fn test_dimension_min_inhabited_time() {
+        let policy: TrimPolicy = toml::from_str(
+            r#"
+            min-inhabited-time = "1m"
+            dimension-min-inhabited-time = { "minecraft:the_nether" = 200 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.get_min_inhabited_time("minecraft:overworld"),
+            Ticks(1200)
+        );
+        assert_eq!(
+            policy
+                .for_dimension("minecraft:the_nether")
+                .min_inhabited_time,
+            Ticks(200)
+        );
+        assert_eq!(policy.to_string(), "1m minecraft:the_nether=10s");
+    }
+}
@@ -0,0 +1,69 @@
+use crate::region_loader::region_position::RegionPosition;
+use std::str::FromStr;
+
+/// A rectangle of blocks given by two opposite corners, such as `-1000,-1000,1000,1000`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Area {
+    pub min_x: i32,
+    pub min_z: i32,
+    pub max_x: i32,
+    pub max_z: i32,
+}
+
+impl Area {
+    /// Whether any block of the region is inside the area
+    pub fn intersects_region(&self, position: RegionPosition) -> bool {
+        let (min_x, min_z) = (position.x * 512, position.z * 512);
+        let (max_x, max_z) = (min_x + 511, min_z + 511);
+        min_x <= self.max_x && max_x >= self.min_x && min_z <= self.max_z && max_z >= self.min_z
+    }
+}
+
+impl FromStr for Area {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coordinates = s
+            .split(',')
+            .map(|coordinate| coordinate.trim().parse::<i32>())
+            .collect::<Result<Vec<_>, _>>();
+        let Ok([x1, z1, x2, z2]) = coordinates.as_deref() else {
+            return Err(String::from(
+                "area must be given as `<x1>,<z1>,<x2>,<z2>` block coordinates",
+            ));
+        };
+
+        Ok(Self {
+            min_x: *x1.min(x2),
+            min_z: *z1.min(z2),
+            max_x: *x1.max(x2),
+            max_z: *z1.max(z2),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intersects_region() {
+        let area = "100,-600,-100,-1".parse::<Area>().unwrap();
+        assert_eq!(
+            area,
+            Area {
+                min_x: -100,
+                min_z: -600,
+                max_x: 100,
+                max_z: -1
+            }
+        );
+
+        assert!(area.intersects_region(RegionPosition { x: -1, z: -1 }));
+        assert!(area.intersects_region(RegionPosition { x: 0, z: -2 }));
+        assert!(!area.intersects_region(RegionPosition { x: 0, z: 0 }));
+        assert!(!area.intersects_region(RegionPosition { x: 1, z: -1 }));
+
+        assert!("1,2,3".parse::<Area>().is_err());
+    }
+}
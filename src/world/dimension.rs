@@ -0,0 +1,103 @@
+use std::path::{Path, PathBuf};
+
+/// A dimension of a world, identified by its namespaced id such as `minecraft:the_nether`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dimension {
+    pub id: String,
+    pub directory: PathBuf,
+}
+
+/// Lists the vanilla dimensions of the world, as well as the datapack and modded dimensions stored
+/// in `dimensions/<namespace>/<path>`
+pub fn get_dimensions(world_dir: &Path) -> Vec<Dimension> {
+    let mut dimensions = vec![
+        Dimension {
+            id: String::from("minecraft:overworld"),
+            directory: world_dir.to_path_buf(),
+        },
+        Dimension {
+            id: String::from("minecraft:the_nether"),
+            directory: world_dir.join("DIM-1"),
+        },
+        Dimension {
+            id: String::from("minecraft:the_end"),
+            directory: world_dir.join("DIM1"),
+        },
+    ];
+
+    for namespace in get_sorted_directories(&world_dir.join("dimensions")) {
+        let Some(namespace_name) = namespace.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let namespace_name = namespace_name.to_string();
+        find_dimensions(&namespace, &namespace_name, "", &mut dimensions);
+    }
+
+    dimensions
+}
+
+/// Dimension paths can be nested, so every directory holding a `region` directory is a dimension
+fn find_dimensions(directory: &Path, namespace: &str, path: &str, dimensions: &mut Vec<Dimension>) {
+    for child in get_sorted_directories(directory) {
+        let Some(name) = child.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if matches!(name, "region" | "entities" | "poi" | "data") {
+            continue;
+        }
+
+        let child_path = if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{path}/{name}")
+        };
+        if child.join("region").is_dir() {
+            dimensions.push(Dimension {
+                id: format!("{namespace}:{child_path}"),
+                directory: child.clone(),
+            });
+        }
+        find_dimensions(&child, namespace, &child_path, dimensions);
+    }
+}
+
+fn get_sorted_directories(directory: &Path) -> Vec<PathBuf> {
+    let mut directories = std::fs::read_dir(directory)
+        .map(|dir| {
+            dir.flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    directories.sort();
+    directories
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_dimensions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let world_dir = temp_dir.path().join("world");
+        let custom = world_dir.join("dimensions/mypack/sky/islands");
+        std::fs::create_dir_all(custom.join("region")).unwrap();
+        std::fs::create_dir_all(world_dir.join("dimensions/mypack/empty")).unwrap();
+
+        let dimensions = get_dimensions(&world_dir);
+
+        let ids = dimensions.iter().map(|d| d.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                "minecraft:overworld",
+                "minecraft:the_nether",
+                "minecraft:the_end",
+                "mypack:sky/islands",
+            ]
+        );
+        assert_eq!(dimensions[3].directory, custom);
+    }
+}
@@ -1,38 +1,95 @@
+use crate::world::dimension::get_dimensions;
 use crate::world::validate::validate_worlds;
 use std::error::Error;
 use std::path::{Path, PathBuf};
 
-pub fn get_region_files(world_paths: &Vec<PathBuf>) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+/// A region file along with the world and the namespaced id of the dimension it belongs to
+pub struct RegionFile {
+    pub world: PathBuf,
+    pub dimension: String,
+    pub path: PathBuf,
+}
+
+/// Lists the region files of the worlds, only keeping the given dimensions unless the list is
+/// empty
+pub fn get_region_files(
+    world_paths: &Vec<PathBuf>,
+    dimensions: &[String],
+) -> Result<Vec<RegionFile>, Box<dyn Error>> {
     let worlds = validate_worlds(world_paths)?;
     Ok(worlds
         .iter()
-        .flat_map(|world| get_region_files_from_world(world))
+        .flat_map(|world| get_region_files_from_world(world, dimensions))
         .collect::<Vec<_>>())
 }
 
-fn get_region_files_from_world(world_dir: &Path) -> Vec<PathBuf> {
-    let mut overworld_regions = get_region_dir(world_dir.to_path_buf());
-    let nether_regions = get_region_dir(world_dir.join("DIM-1"));
-    let the_end_regions = get_region_dir(world_dir.join("DIM1"));
-
-    overworld_regions.extend(nether_regions);
-    overworld_regions.extend(the_end_regions);
-
-    overworld_regions
+fn get_region_files_from_world(world_dir: &Path, dimensions: &[String]) -> Vec<RegionFile> {
+    get_dimensions(world_dir)
+        .into_iter()
+        .filter(|dimension| dimensions.is_empty() || dimensions.contains(&dimension.id))
+        .flat_map(|dimension| {
+            get_region_dir(&dimension.directory)
+                .into_iter()
+                .map(move |path| RegionFile {
+                    world: world_dir.to_path_buf(),
+                    dimension: dimension.id.clone(),
+                    path,
+                })
+        })
+        .collect()
 }
 
-fn get_region_dir(dimension_directory: PathBuf) -> Vec<PathBuf> {
-    get_mca_files(dimension_directory.join("region"))
+fn get_region_dir(dimension_directory: &Path) -> Vec<PathBuf> {
+    get_region_file_paths(dimension_directory.join("region"))
 }
 
-fn get_mca_files(region_directory: PathBuf) -> Vec<PathBuf> {
+/// Lists the Anvil (`.mca`), legacy McRegion (`.mcr`) and Linear (`.linear`) region files
+fn get_region_file_paths(region_directory: PathBuf) -> Vec<PathBuf> {
     std::fs::read_dir(region_directory)
         .map(|dir| {
             dir.flatten()
                 .map(|entry| entry.path())
-                // mcc files are not supported yet
-                .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("mca"))
+                // mcc files are read along with the region file referencing them
+                .filter(|path| {
+                    matches!(
+                        path.extension().and_then(|ext| ext.to_str()),
+                        Some("mca" | "mcr" | "linear")
+                    )
+                })
                 .collect::<Vec<_>>()
         })
         .unwrap_or_default()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_region_file_paths() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let region_directory = temp_dir.path().join("region");
+        std::fs::create_dir_all(&region_directory).unwrap();
+        for file_name in [
+            "r.0.0.mca",
+            "r.0.0.mcr",
+            "r.1.0.linear",
+            "c.0.0.mcc",
+            "r.0.0.mca.tmp",
+        ] {
+            std::fs::write(region_directory.join(file_name), []).unwrap();
+        }
+
+        let mut paths = get_region_file_paths(region_directory.clone());
+        paths.sort();
+
+        assert_eq!(
+            paths,
+            [
+                region_directory.join("r.0.0.mca"),
+                region_directory.join("r.0.0.mcr"),
+                region_directory.join("r.1.0.linear"),
+            ]
+        );
+    }
+}
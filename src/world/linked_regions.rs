@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+
+/// Region folders of a dimension holding data bound to the terrain chunk at the same location,
+/// their chunks are deleted along with the terrain chunks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkedRegion {
+    /// Entities are stored apart from the terrain since 1.17
+    Entities,
+    /// Points of interest such as beds, workstations or nether portals
+    Poi,
+}
+
+impl LinkedRegion {
+    pub const ALL: [LinkedRegion; 2] = [LinkedRegion::Entities, LinkedRegion::Poi];
+
+    pub fn get_directory_name(self) -> &'static str {
+        match self {
+            LinkedRegion::Entities => "entities",
+            LinkedRegion::Poi => "poi",
+        }
+    }
+
+    /// Returns the linked region file at the same coordinates as the given terrain region file
+    pub fn get_region_file(self, region_file_path: &Path) -> Option<PathBuf> {
+        let file_name = region_file_path.file_name()?;
+        let dimension_directory = region_file_path.parent()?.parent()?;
+        Some(
+            dimension_directory
+                .join(self.get_directory_name())
+                .join(file_name),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_region_file() {
+        let region_file_path = PathBuf::from("world/DIM-1/region/r.0.-1.mca");
+
+        assert_eq!(
+            LinkedRegion::Entities.get_region_file(&region_file_path),
+            Some(PathBuf::from("world/DIM-1/entities/r.0.-1.mca"))
+        );
+        assert_eq!(
+            LinkedRegion::Poi.get_region_file(&region_file_path),
+            Some(PathBuf::from("world/DIM-1/poi/r.0.-1.mca"))
+        );
+    }
+}
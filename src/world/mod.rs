@@ -1,2 +1,7 @@
+pub mod area;
+pub mod dimension;
 pub mod get_region_files;
+pub mod linked_regions;
+pub mod server;
+pub mod session_lock;
 pub mod validate;
@@ -0,0 +1,156 @@
+use crate::world::dimension::get_dimensions;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Finds every world of a Bukkit, Spigot or Paper server from its root directory.
+///
+/// The main world is named after the `level-name` property of `server.properties`, and worlds are
+/// stored in the `world-container` directory of `bukkit.yml` if set. Any other world folder, such
+/// as the ones created by multiverse-style plugins, is found as well.
+pub fn find_server_worlds(server_root: &Path) -> Result<Vec<PathBuf>, ServerLayoutError> {
+    if !server_root.is_dir() {
+        return Err(ServerLayoutError::NotADirectory(server_root.to_path_buf()));
+    }
+
+    let level_name = std::fs::read_to_string(server_root.join("server.properties"))
+        .ok()
+        .and_then(|properties| get_property(&properties, "level-name"))
+        .unwrap_or_else(|| String::from("world"));
+    let world_container = std::fs::read_to_string(server_root.join("bukkit.yml"))
+        .ok()
+        .and_then(|bukkit| get_world_container(&bukkit))
+        .map(|container| server_root.join(container))
+        .unwrap_or_else(|| server_root.to_path_buf());
+
+    // Worlds of the main level come first so results are listed in a familiar order
+    let mut worlds = ["", "_nether", "_the_end"]
+        .iter()
+        .map(|suffix| world_container.join(format!("{level_name}{suffix}")))
+        .filter(|world| is_world_directory(world))
+        .collect::<Vec<_>>();
+
+    let mut other_worlds = std::fs::read_dir(&world_container)
+        .map(|dir| {
+            dir.flatten()
+                .map(|entry| entry.path())
+                .filter(|path| !worlds.contains(path) && is_world_directory(path))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    other_worlds.sort();
+    worlds.extend(other_worlds);
+
+    if worlds.is_empty() {
+        return Err(ServerLayoutError::NoWorldFound(server_root.to_path_buf()));
+    }
+
+    Ok(worlds)
+}
+
+/// Whether the directory is one of the worlds found in a server directory, the server root being
+/// either its parent or the parent of its `world-container`
+pub fn is_server_world(directory: &Path) -> bool {
+    directory.ancestors().skip(1).take(2).any(|server_root| {
+        server_root.join("server.properties").is_file()
+            && find_server_worlds(server_root)
+                .is_ok_and(|worlds| worlds.iter().any(|world| world == directory))
+    })
+}
+
+/// Bukkit only keeps the `level.dat` of the main world in some setups, so a folder of the server
+/// holding region files in any of its dimensions is also considered a world
+fn is_world_directory(directory: &Path) -> bool {
+    directory.is_dir()
+        && (directory.join("level.dat").is_file()
+            || get_dimensions(directory)
+                .iter()
+                .any(|dimension| dimension.directory.join("region").is_dir()))
+}
+
+fn get_property(properties: &str, key: &str) -> Option<String> {
+    properties
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#') && !line.starts_with('!'))
+        .filter_map(|line| line.split_once(['=', ':']))
+        .find(|(name, _)| name.trim() == key)
+        .map(|(_, value)| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Reads `settings.world-container` without a full YAML parser, the key is unique in `bukkit.yml`
+fn get_world_container(bukkit: &str) -> Option<String> {
+    bukkit
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.trim().strip_prefix("world-container:"))
+        .map(|value| value.trim().trim_matches(['"', '\'']).to_string())
+        .find(|value| !value.is_empty())
+}
+
+#[derive(Error, Debug)]
+pub enum ServerLayoutError {
+    #[error("the provided server `{0}` directory is not a directory")]
+    NotADirectory(PathBuf),
+    #[error("no world was found in the server directory `{0}`")]
+    NoWorldFound(PathBuf),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_property() {
+        let properties = "#Minecraft server properties\nlevel-seed=\nlevel-name=survival\n";
+        assert_eq!(
+            get_property(properties, "level-name"),
+            Some(String::from("survival"))
+        );
+        assert_eq!(get_property(properties, "level-seed"), None);
+    }
+
+    #[test]
+    fn test_get_world_container() {
+        let bukkit = "settings:\n  allow-end: true\n  world-container: 'worlds'\n";
+        assert_eq!(get_world_container(bukkit), Some(String::from("worlds")));
+        assert_eq!(get_world_container("settings:\n  allow-end: true\n"), None);
+    }
+
+    #[test]
+    fn test_find_server_worlds() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let server_root = temp_dir.path().join("server");
+        let worlds = server_root.join("worlds");
+        std::fs::create_dir_all(worlds.join("survival")).unwrap();
+        std::fs::write(worlds.join("survival/level.dat"), []).unwrap();
+        std::fs::create_dir_all(worlds.join("survival_nether/DIM-1/region")).unwrap();
+        std::fs::create_dir_all(worlds.join("creative/region")).unwrap();
+        std::fs::create_dir_all(worlds.join("plugins")).unwrap();
+        std::fs::write(
+            server_root.join("server.properties"),
+            "level-name=survival\n",
+        )
+        .unwrap();
+        std::fs::write(
+            server_root.join("bukkit.yml"),
+            "settings:\n  world-container: worlds\n",
+        )
+        .unwrap();
+
+        let found = find_server_worlds(&server_root);
+
+        assert_eq!(
+            found.unwrap(),
+            vec![
+                worlds.join("survival"),
+                worlds.join("survival_nether"),
+                worlds.join("creative"),
+            ]
+        );
+        assert!(is_server_world(&worlds.join("survival_nether")));
+        assert!(!is_server_world(&worlds.join("plugins")));
+        // Outside of a server, a folder without `level.dat` is not a world
+        assert!(!is_server_world(&temp_dir.path().join("server/worlds")));
+    }
+}
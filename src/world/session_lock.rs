@@ -0,0 +1,152 @@
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// The lock the game holds on `session.lock` while the world is open. The lock is released when
+/// this is dropped, so it must be kept for the whole run to keep the game from opening the world.
+pub struct SessionLock {
+    _file: File,
+}
+
+/// Locks every world the way the game does, failing if any of them is open
+pub fn lock_worlds(world_paths: &[PathBuf]) -> Result<Vec<SessionLock>, SessionLockError> {
+    world_paths
+        .iter()
+        .map(|world_path| lock_world(world_path))
+        .collect()
+}
+
+fn lock_world(world_path: &Path) -> Result<SessionLock, SessionLockError> {
+    let lock_path = world_path.join("session.lock");
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|err| SessionLockError::Io(lock_path.clone(), err))?;
+
+    match try_lock(&file) {
+        Ok(true) => Ok(SessionLock { _file: file }),
+        Ok(false) => Err(SessionLockError::WorldInUse(world_path.to_path_buf())),
+        Err(err) => Err(SessionLockError::Io(lock_path, err)),
+    }
+}
+
+/// The game uses `FileChannel::tryLock`, which relies on `fcntl` record locks on Unix. These do
+/// not conflict with the `flock` locks used by `File::try_lock`, so `fcntl` has to be used too.
+#[cfg(unix)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: an all-zero `flock` is valid, a zero length locks the whole file
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    // SAFETY: the file descriptor is valid for the lifetime of `file`
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &lock) } == 0 {
+        return Ok(true);
+    }
+
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EACCES | libc::EAGAIN) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    match file.try_lock() {
+        Ok(()) => Ok(true),
+        Err(std::fs::TryLockError::WouldBlock) => Ok(false),
+        Err(std::fs::TryLockError::Error(err)) => Err(err),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SessionLockError {
+    #[error(
+        "the world `{0}` is open in the game or a server, close it before running the trimmer"
+    )]
+    WorldInUse(PathBuf),
+    #[error("cannot lock `{0}`: {1}")]
+    Io(PathBuf, std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    /// The world locked by `hold_lock_in_child_process` when the test binary is run again
+    const CHILD_WORLD_VARIABLE: &str = "TRIMMER_TEST_LOCKED_WORLD";
+    /// Printed by the child once it holds the lock, after the name of the test on the same line
+    const LOCKED_MESSAGE: &str = "world locked";
+
+    #[test]
+    fn test_lock_worlds() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let world_dir = temp_dir.path().join("world");
+        std::fs::create_dir_all(&world_dir).unwrap();
+
+        let locks = lock_worlds(std::slice::from_ref(&world_dir)).unwrap();
+        assert_eq!(locks.len(), 1);
+        assert!(world_dir.join("session.lock").is_file());
+
+        let missing_world = world_dir.join("missing");
+        assert!(matches!(
+            lock_worlds(&[missing_world]),
+            Err(SessionLockError::Io(_, _))
+        ));
+
+        drop(locks);
+    }
+
+    #[test]
+    fn test_world_locked_by_another_process() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let world_dir = temp_dir.path().join("world");
+        std::fs::create_dir_all(&world_dir).unwrap();
+
+        // Record locks are per process, so the lock has to be held by another one
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "world::session_lock::tests::hold_lock_in_child_process",
+                "--exact",
+                "--nocapture",
+            ])
+            .env(CHILD_WORLD_VARIABLE, &world_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        let is_locked = stdout
+            .by_ref()
+            .any(|line| line.is_ok_and(|line| line.ends_with(LOCKED_MESSAGE)));
+        assert!(is_locked);
+
+        let result = lock_worlds(std::slice::from_ref(&world_dir));
+        assert!(matches!(result, Err(SessionLockError::WorldInUse(path)) if path == world_dir));
+
+        // Closing its standard input releases the lock of the child
+        drop(child.stdin.take());
+        stdout.for_each(drop);
+        assert!(child.wait().unwrap().success());
+        assert!(lock_worlds(std::slice::from_ref(&world_dir)).is_ok());
+    }
+
+    /// Holds the lock of a world for `test_world_locked_by_another_process`, does nothing when
+    /// run with the other tests
+    #[test]
+    fn hold_lock_in_child_process() {
+        let Some(world_dir) = std::env::var_os(CHILD_WORLD_VARIABLE) else {
+            return;
+        };
+        let _locks = lock_worlds(&[PathBuf::from(world_dir)]).unwrap();
+        println!("{LOCKED_MESSAGE}");
+        std::io::stdin().lines().for_each(drop);
+    }
+}
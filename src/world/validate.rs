@@ -1,3 +1,4 @@
+use crate::world::server::is_server_world;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
@@ -13,8 +14,12 @@ fn validate_world(world_dir: &Path) -> Result<(), ValidateWorldError> {
         return Err(ValidateWorldError::NotADirectory(world_dir.to_path_buf()));
     }
 
-    if !std::fs::exists(world_dir.join("level.dat")).unwrap_or(false) {
-        return Err(ValidateWorldError::MissingLevelData);
+    // Only the worlds found in a server directory may lack `level.dat`, any other path has to be
+    // the top level of a world
+    if !world_dir.join("level.dat").is_file() && !is_server_world(world_dir) {
+        return Err(ValidateWorldError::MissingLevelData(
+            world_dir.to_path_buf(),
+        ));
     }
 
     Ok(())
@@ -24,6 +29,31 @@ fn validate_world(world_dir: &Path) -> Result<(), ValidateWorldError> {
 pub enum ValidateWorldError {
     #[error("the provided world `{0}` directory is not a directory")]
     NotADirectory(PathBuf),
-    #[error("the provided world directory is missing the `level.dat` file")]
-    MissingLevelData,
+    #[error("the provided world directory `{0}` is missing the `level.dat` file")]
+    MissingLevelData(PathBuf),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_world() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let world_dir = temp_dir.path().join("world");
+        std::fs::create_dir_all(world_dir.join("region")).unwrap();
+
+        // Region files alone do not make a world outside of a server
+        assert!(matches!(
+            validate_world(&world_dir),
+            Err(ValidateWorldError::MissingLevelData(_))
+        ));
+        assert!(matches!(
+            validate_world(&world_dir.join("region")),
+            Err(ValidateWorldError::MissingLevelData(_))
+        ));
+
+        std::fs::write(world_dir.join("level.dat"), []).unwrap();
+        assert!(validate_world(&world_dir).is_ok());
+    }
 }